@@ -0,0 +1,633 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Instant;
+use pleco::{BitBoard, Board, PieceType, Player, SQ};
+use pleco::core::CastleType;
+use pleco::helper::prelude::{bishop_moves, init_statics, king_moves, knight_moves, pawn_attacks_from, queen_moves, rook_moves};
+use crate::core::search::search::{mate_in, mated_in};
+
+/// Distance to mate tables for small endings, generated by retrograde analysis.
+///
+/// Every table is stored from the point of view of the "strong" side, which is laid out as white.
+/// Positions where the strong side is black are probed with the ranks mirrored.
+/// Values are mate scores in plies from the side to move: `TB_MATE - n` mates in `n` plies,
+/// `-TB_MATE + n` gets mated in `n` plies, and 0 is a draw.
+pub const TB_MATE: i16 = 10000;
+const ILLEGAL: i16 = i16::MIN;
+const UNKNOWN: i16 = i16::MIN + 1;
+pub const MAX_PIECES: usize = 4;
+const FILE_MAGIC: &[u8; 4] = b"CTB1";
+const FILE_EXTENSION: &str = "ctb";
+
+/// Endings generated by `generate_all`, in dependency order (captures and promotions of the
+/// later ones lead into the earlier ones)
+pub const GENERATED_ENDINGS: [(&str, &str); 5] = [
+    ("KQ", "K"),
+    ("KR", "K"),
+    ("KP", "K"),
+    ("KBN", "K"),
+    ("KQ", "KR"),
+];
+
+#[derive(Copy, Clone)]
+struct PlacedPiece {
+    strong: bool,
+    piece_type: PieceType,
+    sq: u8,
+}
+
+/// Piece counts of the strong side then the weak side, in the order K, Q, R, B, N, P.
+/// Fixed size so finding the table of a position doesn't allocate.
+type MaterialKey = [u8; 12];
+
+/// A position reduced to its few pieces, enumerated by index during generation.
+/// Generation doesn't go through pleco `Board`s: a `Board` can only be built by parsing a FEN and
+/// sets up its whole state (Zobrist keys, pins, checkers) each time, which is far too slow for the
+/// 2 * 64^4 positions of a 4 piece table. The moves come from the same pleco attack tables, and
+/// the tests check them against `Board::generate_moves`.
+#[derive(Copy, Clone)]
+struct TablePosition {
+    pieces: [PlacedPiece; MAX_PIECES],
+    count: usize,
+    strong_to_move: bool,
+}
+
+impl TablePosition {
+    /// White is laid out as the strong side
+    fn from_board(board: &Board) -> Self {
+        let placeholder = PlacedPiece { strong: true, piece_type: PieceType::K, sq: 0 };
+        let mut position = TablePosition { pieces: [placeholder; MAX_PIECES], count: 0, strong_to_move: board.turn() == Player::White };
+        for player in [Player::White, Player::Black] {
+            for piece_type in [PieceType::K, PieceType::Q, PieceType::R, PieceType::B, PieceType::N, PieceType::P] {
+                for sq in board.piece_bb(player, piece_type) {
+                    position.pieces[position.count] = PlacedPiece { strong: player == Player::White, piece_type, sq: sq.0 };
+                    position.count += 1;
+                }
+            }
+        }
+        position
+    }
+    fn pieces(&self) -> &[PlacedPiece] {
+        &self.pieces[..self.count]
+    }
+    fn occupied(&self) -> BitBoard {
+        self.pieces().iter().fold(BitBoard(0), |bb, p| bb | SQ(p.sq).to_bb())
+    }
+    fn side_occupied(&self, strong: bool) -> BitBoard {
+        self.pieces().iter().filter(|p| p.strong == strong).fold(BitBoard(0), |bb, p| bb | SQ(p.sq).to_bb())
+    }
+    fn king_sq(&self, strong: bool) -> u8 {
+        self.pieces().iter().find(|p| p.strong == strong && p.piece_type == PieceType::K).unwrap().sq
+    }
+    fn is_attacked(&self, sq: u8, by_strong: bool) -> bool {
+        let occupied = self.occupied();
+        self.pieces().iter()
+            .filter(|p| p.strong == by_strong && p.sq != sq)
+            .any(|p| (attacks(p, occupied) & SQ(sq).to_bb()).is_not_empty())
+    }
+    fn in_check(&self, strong: bool) -> bool {
+        self.is_attacked(self.king_sq(strong), !strong)
+    }
+    fn is_legal(&self) -> bool {
+        let occupied = self.occupied();
+        if occupied.count_bits() as usize != self.count {
+            return false;
+        }
+        let pawn_on_back_rank = self.pieces().iter()
+            .any(|p| p.piece_type == PieceType::P && (p.sq < 8 || p.sq >= 56));
+        !pawn_on_back_rank && !self.in_check(!self.strong_to_move)
+    }
+    /// Calls `f` with every position reachable by a legal move.
+    /// Promotions are only to queen and rook, as minor pieces can't do better here.
+    fn for_each_child<F: FnMut(TablePosition)>(&self, mut f: F) {
+        let us = self.strong_to_move;
+        let occupied = self.occupied();
+        let own = self.side_occupied(us);
+        let enemy = self.side_occupied(!us);
+
+        for i in 0..self.count {
+            let piece = self.pieces[i];
+            if piece.strong != us {
+                continue;
+            }
+            let targets = if piece.piece_type == PieceType::P {
+                pawn_pushes(&piece, occupied) | (attacks(&piece, occupied) & enemy)
+            } else {
+                attacks(&piece, occupied) & !own
+            };
+            for dest in targets {
+                let dest = dest.0;
+                let mut child = *self;
+                child.strong_to_move = !us;
+                if let Some(j) = child.pieces().iter().position(|p| p.sq == dest) {
+                    child.pieces[j] = child.pieces[child.count - 1];
+                    child.count -= 1;
+                }
+                let index = child.pieces().iter().position(|p| p.sq == piece.sq).unwrap();
+                child.pieces[index].sq = dest;
+
+                let promotes = piece.piece_type == PieceType::P && (dest >= 56 || dest < 8);
+                if promotes {
+                    for promotion in [PieceType::Q, PieceType::R] {
+                        child.pieces[index].piece_type = promotion;
+                        if !child.in_check(us) {
+                            f(child);
+                        }
+                    }
+                } else if !child.in_check(us) {
+                    f(child);
+                }
+            }
+        }
+    }
+}
+
+fn attacks(piece: &PlacedPiece, occupied: BitBoard) -> BitBoard {
+    let sq = SQ(piece.sq);
+    match piece.piece_type {
+        PieceType::K => king_moves(sq),
+        PieceType::Q => queen_moves(occupied, sq),
+        PieceType::R => rook_moves(occupied, sq),
+        PieceType::B => bishop_moves(occupied, sq),
+        PieceType::N => knight_moves(sq),
+        PieceType::P => pawn_attacks_from(sq, if piece.strong { Player::White } else { Player::Black }),
+        _ => BitBoard(0),
+    }
+}
+fn pawn_pushes(pawn: &PlacedPiece, occupied: BitBoard) -> BitBoard {
+    let forward: i8 = if pawn.strong { 8 } else { -8 };
+    let start_rank = if pawn.strong { 1 } else { 6 };
+    let mut pushes = BitBoard(0);
+    let single = (pawn.sq as i8 + forward) as u8;
+    if (SQ(single).to_bb() & occupied).is_empty() {
+        pushes = pushes | SQ(single).to_bb();
+        let double = (single as i8 + forward) as u8;
+        if pawn.sq / 8 == start_rank && (SQ(double).to_bb() & occupied).is_empty() {
+            pushes = pushes | SQ(double).to_bb();
+        }
+    }
+    pushes
+}
+fn piece_char(piece_type: PieceType) -> char {
+    match piece_type {
+        PieceType::K => 'K',
+        PieceType::Q => 'Q',
+        PieceType::R => 'R',
+        PieceType::B => 'B',
+        PieceType::N => 'N',
+        PieceType::P => 'P',
+        _ => '?',
+    }
+}
+fn char_piece(c: char) -> Option<PieceType> {
+    match c {
+        'K' => Some(PieceType::K),
+        'Q' => Some(PieceType::Q),
+        'R' => Some(PieceType::R),
+        'B' => Some(PieceType::B),
+        'N' => Some(PieceType::N),
+        'P' => Some(PieceType::P),
+        _ => None,
+    }
+}
+fn material_key(pieces: impl Iterator<Item=(bool, PieceType)>) -> MaterialKey {
+    let mut key = [0; 12];
+    for (strong, piece_type) in pieces {
+        let slot = match piece_type {
+            PieceType::K => 0,
+            PieceType::Q => 1,
+            PieceType::R => 2,
+            PieceType::B => 3,
+            PieceType::N => 4,
+            _ => 5,
+        };
+        key[if strong { slot } else { 6 + slot }] += 1;
+    }
+    key
+}
+/// Same material with the sides swapped
+fn swap_sides(key: MaterialKey) -> MaterialKey {
+    let mut swapped = [0; 12];
+    swapped[..6].copy_from_slice(&key[6..]);
+    swapped[6..].copy_from_slice(&key[..6]);
+    swapped
+}
+/// Turns a value seen from a child position into the value for the parent
+fn negate_and_shift(value: i16) -> i16 {
+    let value = -value;
+    if value > 0 {
+        value - 1
+    } else if value < 0 {
+        value + 1
+    } else {
+        0
+    }
+}
+fn plies(value: i16) -> i16 {
+    TB_MATE - value.abs()
+}
+
+pub struct EndgameTable {
+    strong: Vec<PieceType>,
+    weak: Vec<PieceType>,
+    values: Vec<i16>,
+}
+
+impl EndgameTable {
+    pub fn name(&self) -> String {
+        format!("{}v{}",
+                self.strong.iter().map(|&p| piece_char(p)).collect::<String>(),
+                self.weak.iter().map(|&p| piece_char(p)).collect::<String>())
+    }
+    fn piece_count(&self) -> usize {
+        self.strong.len() + self.weak.len()
+    }
+    fn layout(&self) -> impl Iterator<Item=(bool, PieceType)> + '_ {
+        self.strong.iter().map(|&p| (true, p)).chain(self.weak.iter().map(|&p| (false, p)))
+    }
+    fn layout_at(&self, i: usize) -> (bool, PieceType) {
+        match self.strong.get(i) {
+            Some(&piece_type) => (true, piece_type),
+            None => (false, self.weak[i - self.strong.len()]),
+        }
+    }
+    fn material_key(&self) -> MaterialKey {
+        material_key(self.layout())
+    }
+    /// `pieces` must be in the same order as `layout`
+    fn index(&self, position: &TablePosition) -> usize {
+        position.pieces().iter().fold(!position.strong_to_move as usize, |index, p| index * 64 + p.sq as usize)
+    }
+    fn position(&self, mut index: usize) -> TablePosition {
+        let count = self.piece_count();
+        let placeholder = PlacedPiece { strong: true, piece_type: PieceType::K, sq: 0 };
+        let mut position = TablePosition { pieces: [placeholder; MAX_PIECES], count, strong_to_move: true };
+        for i in (0..count).rev() {
+            let (strong, piece_type) = self.layout_at(i);
+            position.pieces[i] = PlacedPiece { strong, piece_type, sq: (index % 64) as u8 };
+            index /= 64;
+        }
+        position.strong_to_move = index == 0;
+        position
+    }
+    /// Reorders the pieces to match the table layout
+    fn arrange(&self, position: &TablePosition) -> TablePosition {
+        let mut arranged = *position;
+        let mut used = [false; MAX_PIECES];
+        for (i, (strong, piece_type)) in self.layout().enumerate() {
+            let j = (0..position.count)
+                .find(|&j| !used[j] && position.pieces[j].strong == strong && position.pieces[j].piece_type == piece_type)
+                .unwrap();
+            used[j] = true;
+            arranged.pieces[i] = position.pieces[j];
+        }
+        arranged
+    }
+
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let mut file = fs::File::create(dir.join(format!("{}.{}", self.name(), FILE_EXTENSION)))?;
+        let strong: String = self.strong.iter().map(|&p| piece_char(p)).collect();
+        let weak: String = self.weak.iter().map(|&p| piece_char(p)).collect();
+        let mut bytes = Vec::with_capacity(self.values.len() * 2 + 16);
+        bytes.extend_from_slice(FILE_MAGIC);
+        bytes.push(strong.len() as u8);
+        bytes.extend_from_slice(strong.as_bytes());
+        bytes.push(weak.len() as u8);
+        bytes.extend_from_slice(weak.as_bytes());
+        for value in &self.values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        file.write_all(&bytes)
+    }
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), msg));
+        let mut bytes = vec![];
+        fs::File::open(path)?.read_to_end(&mut bytes)?;
+        if bytes.len() < 6 || &bytes[..4] != FILE_MAGIC {
+            return Err(invalid("not an endgame table"));
+        }
+        let mut offset = 4;
+        let read_signature = |offset: &mut usize| -> Option<Vec<PieceType>> {
+            let len = *bytes.get(*offset)? as usize;
+            let sig = bytes.get(*offset + 1..*offset + 1 + len)?;
+            *offset += 1 + len;
+            sig.iter().map(|&c| char_piece(c as char)).collect()
+        };
+        let strong = read_signature(&mut offset).ok_or_else(|| invalid("bad signature"))?;
+        let weak = read_signature(&mut offset).ok_or_else(|| invalid("bad signature"))?;
+        let values: Vec<i16> = bytes[offset..].chunks_exact(2).map(|c| i16::from_le_bytes([c[0], c[1]])).collect();
+        if values.len() != 2 * 64usize.pow((strong.len() + weak.len()) as u32) {
+            return Err(invalid("wrong table size"));
+        }
+        Ok(Self { strong, weak, values })
+    }
+}
+
+pub struct EndgameTables {
+    tables: HashMap<MaterialKey, EndgameTable>,
+}
+
+impl EndgameTables {
+    pub fn new() -> Self {
+        Self {
+            tables: HashMap::new(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+    /// Loads every table file in the directory, replacing the tables loaded before.
+    /// Returns the number of tables loaded.
+    pub fn load_dir(&mut self, dir: &str) -> io::Result<usize> {
+        self.tables.clear();
+        let mut count = 0;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |e| e == FILE_EXTENSION) {
+                self.insert(EndgameTable::load(&path)?);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+    pub fn clear(&mut self) {
+        self.tables.clear();
+    }
+    fn insert(&mut self, table: EndgameTable) {
+        self.tables.insert(table.material_key(), table);
+    }
+    /// Value of a position laid out with the strong side as white, if a table covers its material.
+    /// The position is mirrored if it is covered with the colors swapped.
+    fn value_of(&self, position: &TablePosition) -> Option<i16> {
+        let key = material_key(position.pieces().iter().map(|p| (p.strong, p.piece_type)));
+        if let Some(table) = self.tables.get(&key) {
+            return Some(table.values[table.index(&table.arrange(position))]);
+        }
+        let table = self.tables.get(&swap_sides(key))?;
+        let mut mirrored = *position;
+        mirrored.strong_to_move = !position.strong_to_move;
+        for p in mirrored.pieces[..mirrored.count].iter_mut() {
+            p.strong = !p.strong;
+            p.sq ^= 56;
+        }
+        Some(table.values[table.index(&table.arrange(&mirrored))])
+    }
+    /// Probes the board, returning the distance to mate value from the side to move if a table covers it
+    pub fn probe(&self, board: &Board) -> Option<i16> {
+        if self.tables.is_empty() || board.occupied().count_bits() as usize > MAX_PIECES {
+            return None;
+        }
+        for player in [Player::White, Player::Black] {
+            if board.can_castle(player, CastleType::KingSide) || board.can_castle(player, CastleType::QueenSide) {
+                return None;
+            }
+        }
+        self.value_of(&TablePosition::from_board(board)).filter(|&v| v != ILLEGAL && v != UNKNOWN)
+    }
+
+    /// Generates the table for the given material, e.g. ("KQ", "KR").
+    /// Captures and promotions are looked up in the tables generated before, or scored as draws
+    /// if no table covers them.
+    pub fn generate(&self, strong: &str, weak: &str) -> EndgameTable {
+        init_statics();
+        let mut table = EndgameTable {
+            strong: strong.chars().filter_map(char_piece).collect(),
+            weak: weak.chars().filter_map(char_piece).collect(),
+            values: vec![],
+        };
+        let size = 2 * 64usize.pow(table.piece_count() as u32);
+        table.values = vec![UNKNOWN; size];
+        let dependency_max_plies = self.tables.values()
+            .flat_map(|t| t.values.iter())
+            .filter(|&&v| v != 0 && v != ILLEGAL && v != UNKNOWN)
+            .map(|&v| plies(v))
+            .max()
+            .unwrap_or(0);
+
+        // Illegal positions, mates and stalemates
+        for index in 0..size {
+            let position = table.position(index);
+            if !position.is_legal() {
+                table.values[index] = ILLEGAL;
+                continue;
+            }
+            let mut has_move = false;
+            position.for_each_child(|_| has_move = true);
+            if !has_move {
+                table.values[index] = if position.in_check(position.strong_to_move) { -TB_MATE } else { 0 };
+            }
+        }
+
+        // Mates in `ply` plies are resolved at iteration `ply`, so the first value found is the shortest
+        let mut ply: i16 = 1;
+        loop {
+            let mut changed = false;
+            for index in 0..size {
+                if table.values[index] != UNKNOWN {
+                    continue;
+                }
+                let position = table.position(index);
+                let mut best = i16::MIN;
+                let mut all_children_win = true;
+                position.for_each_child(|child| {
+                    let child_value = if child.count == table.piece_count() && child.pieces().iter().zip(table.layout()).all(|(p, (s, t))| p.strong == s && p.piece_type == t) {
+                        table.values[table.index(&child)]
+                    } else {
+                        self.value_of(&child).unwrap_or(0)
+                    };
+                    if child_value == UNKNOWN || child_value == ILLEGAL {
+                        all_children_win = false;
+                        return;
+                    }
+                    if child_value <= 0 {
+                        all_children_win = false;
+                    }
+                    best = best.max(negate_and_shift(child_value));
+                });
+
+                let resolved = (best > 0 && plies(best) <= ply) || (all_children_win && plies(best) <= ply);
+                if resolved {
+                    table.values[index] = best;
+                    changed = true;
+                }
+            }
+            if !changed && ply > dependency_max_plies {
+                break;
+            }
+            ply += 1;
+        }
+
+        for value in table.values.iter_mut() {
+            if *value == UNKNOWN {
+                *value = 0;
+            }
+        }
+        table
+    }
+    /// Generates every ending in `GENERATED_ENDINGS` and saves the tables to `dir`
+    pub fn generate_all(&mut self, dir: &str) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        for (strong, weak) in GENERATED_ENDINGS {
+            let start = Instant::now();
+            let table = self.generate(strong, weak);
+            table.save(Path::new(dir))?;
+            let longest = table.values.iter().filter(|&&v| v > 0).map(|&v| plies(v)).max().unwrap_or(0);
+            println!("Generated {} in {:?}, longest mate {} plies", table.name(), start.elapsed(), longest);
+            self.insert(table);
+        }
+        Ok(())
+    }
+}
+
+/// Converts a table value into a search eval, adjusting the mate distance for the ply of the probe
+pub fn table_value_to_eval(value: i16, ply_from_root: u8) -> i32 {
+    if value > 0 {
+        mate_in(ply_from_root.saturating_add(plies(value) as u8))
+    } else if value < 0 {
+        mated_in(ply_from_root.saturating_add(plies(value) as u8))
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::LazyLock;
+    use pleco::{Board, PieceType};
+    use pleco::helper::prelude::init_statics;
+    use super::{material_key, plies, swap_sides, table_value_to_eval, EndgameTable, EndgameTables, TablePosition, TB_MATE};
+    use crate::core::search::search::{mate_in, mated_in};
+
+    /// Generated once, it takes a while in debug builds
+    static KQK: LazyLock<EndgameTables> = LazyLock::new(|| {
+        let mut tables = EndgameTables::new();
+        let table = tables.generate("KQ", "K");
+        tables.insert(table);
+        tables
+    });
+
+    /// KQvK positions with their distance to mate value
+    const KQK_VALUES: [(&str, i16); 5] = [
+        // Qh8# and Qb7#
+        ("k7/8/1K6/8/8/8/8/7Q w - - 0 1", TB_MATE - 1),
+        ("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1", -TB_MATE),
+        ("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1", 0),
+        // The first position with the colors swapped
+        ("7q/8/8/8/8/1k6/8/K7 b - - 0 1", TB_MATE - 1),
+        // Black takes the undefended queen
+        ("8/8/8/8/8/8/1kQ5/7K b - - 0 1", 0),
+    ];
+
+    /// Table whose values are derived from their index, to tell which entry a probe reads
+    fn indexed_table(strong: Vec<PieceType>, weak: Vec<PieceType>) -> EndgameTable {
+        let size = 2 * 64usize.pow((strong.len() + weak.len()) as u32);
+        EndgameTable { strong, weak, values: (0..size).map(|i| (i % 5000) as i16).collect() }
+    }
+
+    #[test]
+    fn moves_match_the_board() {
+        init_statics();
+        let fens = [
+            "8/8/8/4k3/8/8/8/4K2Q w - - 0 1",
+            "8/8/8/4k3/8/8/8/4K2Q b - - 0 1",
+            "8/8/8/3k4/8/8/8/KBN5 w - - 0 1",
+            "8/4P3/8/8/2k5/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/3q4/4K3 w - - 0 1",
+            "1k6/8/8/8/8/8/r7/1R2K3 b - - 0 1",
+            "8/8/8/8/8/5k2/4p3/6K1 b - - 0 1",
+        ];
+        for fen in fens {
+            let board = Board::from_fen(fen).unwrap();
+            // Tables only promote to queen or rook
+            let expected = board.generate_moves().iter()
+                .filter(|m| !m.is_promo() || matches!(m.promo_piece(), PieceType::Q | PieceType::R))
+                .count();
+            let mut children = 0;
+            TablePosition::from_board(&board).for_each_child(|_| children += 1);
+            assert_eq!(children, expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn material_key_counts_each_side() {
+        let key = material_key([(true, PieceType::K), (true, PieceType::Q), (false, PieceType::K), (false, PieceType::R)].into_iter());
+        assert_eq!(key, [1, 1, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0]);
+        assert_eq!(swap_sides(key), [1, 0, 1, 0, 0, 0, 1, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn colors_swapped_positions_probe_the_same_entry() {
+        let mut tables = EndgameTables::new();
+        tables.insert(indexed_table(vec![PieceType::K, PieceType::N], vec![PieceType::K]));
+        let white = Board::from_fen("8/8/8/3k4/8/8/8/KN6 w - - 0 1").unwrap();
+        let black = Board::from_fen("kn6/8/8/8/3K4/8/8/8 b - - 0 1").unwrap();
+        assert!(tables.probe(&white).is_some());
+        assert_eq!(tables.probe(&white), tables.probe(&black));
+    }
+
+    #[test]
+    fn uncovered_material_is_not_probed() {
+        let mut tables = EndgameTables::new();
+        tables.insert(indexed_table(vec![PieceType::K, PieceType::N], vec![PieceType::K]));
+        assert_eq!(tables.probe(&Board::from_fen("8/8/8/3k4/8/8/8/KR6 w - - 0 1").unwrap()), None);
+        tables.clear();
+        assert_eq!(tables.probe(&Board::from_fen("8/8/8/3k4/8/8/8/KN6 w - - 0 1").unwrap()), None);
+    }
+
+    #[test]
+    fn table_values_become_mate_scores_from_the_probe_ply() {
+        assert_eq!(table_value_to_eval(TB_MATE - 3, 4), mate_in(7));
+        assert_eq!(table_value_to_eval(-TB_MATE + 2, 4), mated_in(6));
+        assert_eq!(table_value_to_eval(0, 4), 0);
+    }
+
+    #[test]
+    fn generated_kqk_values() {
+        for (fen, value) in KQK_VALUES {
+            assert_eq!(KQK.probe(&Board::from_fen(fen).unwrap()), Some(value), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn longest_kqk_win_is_mate_in_ten() {
+        let table = KQK.tables.values().next().unwrap();
+        let longest = table.values.iter().filter(|&&v| v > 0).map(|&v| plies(v)).max();
+        assert_eq!(longest, Some(19));
+    }
+
+    #[test]
+    fn saved_tables_load_back() {
+        let dir = std::env::temp_dir().join(format!("endgame_tables_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        KQK.tables.values().next().unwrap().save(&dir).unwrap();
+
+        let mut loaded = EndgameTables::new();
+        assert_eq!(loaded.load_dir(dir.to_str().unwrap()).unwrap(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let (original, reloaded) = (KQK.tables.values().next().unwrap(), loaded.tables.values().next().unwrap());
+        assert_eq!(reloaded.name(), "KQvK");
+        assert!(original.values == reloaded.values);
+        for (fen, _) in KQK_VALUES {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(loaded.probe(&board), KQK.probe(&board), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn truncated_tables_are_rejected() {
+        let dir = std::env::temp_dir().join(format!("endgame_tables_truncated_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        KQK.tables.values().next().unwrap().save(&dir).unwrap();
+        let path = dir.join("KQvK.ctb");
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 2]).unwrap();
+        let result = EndgameTable::load(&path);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_err());
+    }
+}
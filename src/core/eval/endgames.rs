@@ -0,0 +1,167 @@
+use pleco::{Board, PieceType, Player};
+use crate::core::eval::params::EvalParams;
+
+/// Scale factors go from 0 (dead draw) to `SCALE_NORMAL` (the endgame score is kept as is)
+pub const SCALE_NORMAL: i32 = 64;
+
+/// Material of one side, used to recognize known endings
+#[derive(Copy, Clone)]
+struct Material {
+    pawns: u8,
+    knights: u8,
+    bishops: u8,
+    rooks: u8,
+    queens: u8,
+}
+
+impl Material {
+    fn of(board: &Board, color: Player) -> Self {
+        Self {
+            pawns: board.count_piece(color, PieceType::P),
+            knights: board.count_piece(color, PieceType::N),
+            bishops: board.count_piece(color, PieceType::B),
+            rooks: board.count_piece(color, PieceType::R),
+            queens: board.count_piece(color, PieceType::Q),
+        }
+    }
+    fn is_lone_king(&self) -> bool {
+        self.pawns + self.non_pawn_count() == 0
+    }
+    fn non_pawn_count(&self) -> u8 {
+        self.knights + self.bishops + self.rooks + self.queens
+    }
+    /// Endgame value of the pieces
+    fn non_pawn_value(&self, params: &EvalParams) -> i32 {
+        self.knights as i32 * params.knight_value.eg() + self.bishops as i32 * params.bishop_value.eg()
+            + self.rooks as i32 * params.rook_value.eg() + self.queens as i32 * params.queen_value.eg()
+    }
+    fn value(&self, params: &EvalParams) -> i32 {
+        self.non_pawn_value(params) + self.pawns as i32 * params.pawn_value.eg()
+    }
+    /// Enough to mate a lone king without help from the pawns
+    fn can_force_mate(&self) -> bool {
+        self.queens > 0 || self.rooks > 0 || self.bishops >= 2 || (self.bishops >= 1 && self.knights >= 1)
+    }
+    /// Can't mate even with the help of the opponent
+    fn is_insufficient(&self) -> bool {
+        self.pawns == 0 && self.rooks == 0 && self.queens == 0 && self.knights + self.bishops <= 1
+    }
+}
+
+fn file_of(sq: u8) -> i32 {
+    (sq % 8) as i32
+}
+fn rank_of(sq: u8) -> i32 {
+    (sq / 8) as i32
+}
+fn is_dark_square(sq: u8) -> bool {
+    (file_of(sq) + rank_of(sq)) % 2 == 0
+}
+fn manhattan_distance(a: u8, b: u8) -> i32 {
+    (file_of(a) - file_of(b)).abs() + (rank_of(a) - rank_of(b)).abs()
+}
+fn chebyshev_distance(a: u8, b: u8) -> i32 {
+    (file_of(a) - file_of(b)).abs().max((rank_of(a) - rank_of(b)).abs())
+}
+/// 0 on the four center squares up to 6 in the corners
+fn center_manhattan_distance(sq: u8) -> i32 {
+    let file = file_of(sq);
+    let rank = rank_of(sq);
+    (3 - file).max(file - 4) + (3 - rank).max(rank - 4)
+}
+
+/// Evaluates known endings, from white's point of view.
+/// Returns `None` if the position isn't one, so the general evaluation should be used.
+pub fn evaluate_endgame(board: &Board, params: &EvalParams) -> Option<i32> {
+    let white = Material::of(board, Player::White);
+    let black = Material::of(board, Player::Black);
+
+    if white.is_insufficient() && black.is_insufficient() {
+        return Some(0);
+    }
+
+    for (strong_color, strong, weak) in [(Player::White, white, black), (Player::Black, black, white)] {
+        let sign = if strong_color == Player::White { 1 } else { -1 };
+
+        if weak.is_lone_king() && strong.is_insufficient() {
+            return Some(0);
+        }
+        if weak.is_lone_king() && strong.pawns == 0 && strong.can_force_mate() {
+            return Some(sign * mop_up(board, strong_color, strong, params));
+        }
+        if weak.is_lone_king() && is_wrong_rook_pawn_draw(board, strong_color, strong) {
+            return Some(0);
+        }
+    }
+    None
+}
+
+/// Drives the lone king to the edge (or the right corner for KBNK) and brings the winning king closer
+fn mop_up(board: &Board, strong_color: Player, strong: Material, params: &EvalParams) -> i32 {
+    let strong_king = board.king_sq(strong_color).0;
+    let weak_king = board.king_sq(!strong_color).0;
+
+    let mut eval = strong.value(params);
+    eval += params.kings_proximity_weight * (14 - manhattan_distance(strong_king, weak_king));
+
+    let is_kbnk = strong.bishops == 1 && strong.knights == 1 && strong.rooks == 0 && strong.queens == 0;
+    if is_kbnk {
+        // Mate is only possible in a corner of the bishop's color
+        let bishop = board.piece_bb(strong_color, PieceType::B).bsf().0;
+        let corners: [u8; 2] = if is_dark_square(bishop) { [0, 63] } else { [7, 56] };
+        let corner_distance = corners.iter().map(|&c| manhattan_distance(weak_king, c)).min().unwrap();
+        eval += params.kbnk_corner_weight * (14 - corner_distance);
+    } else {
+        eval += params.losing_king_center_distance_weight * center_manhattan_distance(weak_king);
+    }
+    eval
+}
+
+/// Bishop and rook pawns against a lone king, where the bishop can't cover the promotion square
+/// and the defending king already sits in front of it
+fn is_wrong_rook_pawn_draw(board: &Board, strong_color: Player, strong: Material) -> bool {
+    if strong.pawns == 0 || strong.bishops != 1 || strong.knights + strong.rooks + strong.queens != 0 {
+        return false;
+    }
+    let pawns = board.piece_bb(strong_color, PieceType::P).0;
+    const FILE_A: u64 = 0x0101_0101_0101_0101;
+    const FILE_H: u64 = FILE_A << 7;
+    let file = if pawns & !FILE_A == 0 {
+        0
+    } else if pawns & !FILE_H == 0 {
+        7
+    } else {
+        return false;
+    };
+
+    let promotion_sq = if strong_color == Player::White { 56 + file } else { file };
+    let bishop = board.piece_bb(strong_color, PieceType::B).bsf().0;
+    let weak_king = board.king_sq(!strong_color).0;
+    is_dark_square(bishop) != is_dark_square(promotion_sq) && chebyshev_distance(weak_king, promotion_sq) <= 1
+}
+
+/// How much of the endgame score the side ahead can expect to convert
+pub fn scale_factor(board: &Board, strong_color: Player, params: &EvalParams) -> i32 {
+    let strong = Material::of(board, strong_color);
+    let weak = Material::of(board, !strong_color);
+
+    let only_bishops = |m: &Material| m.bishops == 1 && m.knights + m.rooks + m.queens == 0;
+    let one_bishop = |m: &Material| m.bishops == 1;
+    if one_bishop(&strong) && one_bishop(&weak) {
+        let strong_bishop = board.piece_bb(strong_color, PieceType::B).bsf().0;
+        let weak_bishop = board.piece_bb(!strong_color, PieceType::B).bsf().0;
+        if is_dark_square(strong_bishop) != is_dark_square(weak_bishop) {
+            return if only_bishops(&strong) && only_bishops(&weak) {
+                params.scale_opposite_bishops
+            } else {
+                params.scale_opposite_bishops_with_pieces
+            };
+        }
+    }
+
+    // Without pawns, being up less than a rook is usually not enough to win
+    if strong.pawns == 0 && strong.non_pawn_value(params) - weak.non_pawn_value(params) <= params.bishop_value.eg() {
+        return params.scale_no_pawns_small_advantage;
+    }
+    SCALE_NORMAL
+}
@@ -0,0 +1,46 @@
+use std::mem::size_of;
+
+pub const DEFAULT_EVAL_CACHE_MB: usize = 4;
+
+#[derive(Copy, Clone, Default)]
+struct EvalCacheEntry {
+    key: u64,
+    eval: i32,
+}
+
+/// Remembers the static evaluation of recently seen positions by their Zobrist key,
+/// from the point of view of the side to move. Each key maps to a single slot and a new
+/// evaluation always replaces the old one.
+/// Must be cleared when the evaluator changes.
+pub struct EvalCache {
+    entries: Vec<EvalCacheEntry>,
+}
+
+impl EvalCache {
+    /// Largest power of two number of entries fitting in `size_mb`; 0 disables the cache
+    pub fn new(size_mb: usize) -> Self {
+        let count = size_mb * 1024 * 1024 / size_of::<EvalCacheEntry>();
+        let count = if count == 0 { 0 } else { 1 << count.ilog2() };
+        Self {
+            entries: vec![EvalCacheEntry::default(); count],
+        }
+    }
+    pub fn clear(&mut self) {
+        self.entries.fill(EvalCacheEntry::default());
+    }
+    pub fn probe(&self, key: u64) -> Option<i32> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let entry = self.entries[key as usize & (self.entries.len() - 1)];
+        // Key 0 marks an empty slot
+        (entry.key == key && key != 0).then_some(entry.eval)
+    }
+    pub fn store(&mut self, key: u64, eval: i32) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let index = key as usize & (self.entries.len() - 1);
+        self.entries[index] = EvalCacheEntry { key, eval };
+    }
+}
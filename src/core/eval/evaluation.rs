@@ -1,62 +1,138 @@
-use pleco::{BitMove, Board, Piece, PieceType, Player};
+use pleco::{Board, PieceType, Player};
 use crate::core::eval::Evaluator;
+use crate::core::eval::endgames::{evaluate_endgame, scale_factor, SCALE_NORMAL};
+use crate::core::eval::incremental::{IncrementalEval, COLORS, PIECE_TYPES};
+use crate::core::eval::king_safety::evaluate_king_safety;
+use crate::core::eval::params::EvalParams;
+use crate::core::eval::pawns::PawnHashTable;
+use crate::core::eval::pieces::evaluate_pieces;
 use crate::core::eval::piece_square_tables::PieceSquareTables;
+use crate::core::eval::score::{Score, MAX_PHASE};
+use crate::core::eval::trace::{EvalTrace, TermAccumulator};
 
+/// Material defaults, see `EvalParams::default`
 pub const PAWN_VALUE: i32 = 100;
 pub const KNIGHT_VALUE: i32 = 300;
 pub const BISHOP_VALUE: i32 = 320;
 pub const ROOK_VALUE: i32 = 500;
 pub const QUEEN_VALUE: i32 = 900;
-pub const ENDGAME_MATERIAL_START: i32 = ROOK_VALUE * 2 + BISHOP_VALUE + KNIGHT_VALUE;
 
-
-pub struct ClassicEvaluator;
+pub struct ClassicEvaluator {
+    params: EvalParams,
+    pawn_table: PawnHashTable,
+    incremental: IncrementalEval,
+}
 
 impl Evaluator for ClassicEvaluator {
     fn evaluate_board(&mut self, board: &Board) -> i32 {
         let perspective = if board.turn() == Player::White { 1 } else { -1 };
+        if let Some(eval) = evaluate_endgame(board, &self.params) {
+            return eval * perspective;
+        }
+        let phase = self.game_phase(board);
 
-
-        let white_material = ClassicEvaluator::count_material(&board, Player::White);
-        let black_material = ClassicEvaluator::count_material(&board, Player::Black);
-
-        let white_material_without_pawns = white_material - board.count_piece(Player::White, PieceType::P) as i32;
-        let black_material_without_pawns = black_material - board.count_piece(Player::Black, PieceType::P) as i32;
-
-        let white_endgame_weight = Self::endgame_phase_weight(white_material_without_pawns);
-        let black_endgame_weight = Self::endgame_phase_weight(black_material_without_pawns);
-
-        let white_piece_square = (Self::evaluate_piece_square(board, white_endgame_weight, Player::White) * 2f32).round() as i32;
-        let black_piece_square = (Self::evaluate_piece_square(board, black_endgame_weight, Player::Black) * 2f32).round() as i32;
-        let mut white_eval = 0;
-        let mut black_eval = 0;
-
-        white_eval += white_material;
-        white_eval += white_piece_square;
-
-        black_eval += black_material;
-        black_eval += black_piece_square;
-        let eval = (white_eval - black_eval) * perspective;
-        eval
+        let score = self.evaluate_score(board, None);
+        score.taper_scaled(phase, self.scale_factor(board, score), SCALE_NORMAL) * perspective
+    }
+    fn make_move(&mut self, board: &Board) {
+        self.incremental.make_move(board, &self.params);
+    }
+    fn unmake_move(&mut self) {
+        self.incremental.unmake_move();
+    }
+    fn piece_value(&self, piece: PieceType) -> i32 {
+        self.params.piece_value(piece).mg()
     }
 }
 impl ClassicEvaluator {
     pub fn new() -> Self {
-        Self{}
+        Self::with_params(EvalParams::default())
+    }
+    pub fn with_params(params: EvalParams) -> Self {
+        Self {
+            params,
+            pawn_table: PawnHashTable::new(),
+            incremental: IncrementalEval::new(),
+        }
+    }
+    pub fn params(&self) -> &EvalParams {
+        &self.params
+    }
+    pub fn set_params(&mut self, params: EvalParams) {
+        self.params = params;
+        self.pawn_table.clear();
+        self.incremental.clear();
+    }
+    /// Evaluation broken down by term for both sides
+    pub fn trace(&mut self, board: &Board) -> EvalTrace {
+        let mut trace = EvalTrace::new(self.game_phase(board));
+        trace.endgame = evaluate_endgame(board, &self.params);
+        let score = self.evaluate_score(board, Some(&mut trace));
+        trace.scale = self.scale_factor(board, score);
+        trace
+    }
+    /// Scale factor for the side that is ahead in the endgame
+    fn scale_factor(&self, board: &Board, score: Score) -> i32 {
+        scale_factor(board, if score.eg() >= 0 { Player::White } else { Player::Black }, &self.params)
     }
-    fn count_material(board: &Board, color: Player) -> i32 {
-        let mut material = 0;
+    /// Untapered evaluation from white's point of view
+    fn evaluate_score(&mut self, board: &Board, trace: Option<&mut EvalTrace>) -> Score {
+        let params = &self.params;
+        let mut terms = TermAccumulator::new(trace);
+
+        let base = self.incremental.current(board, params);
+        debug_assert!(COLORS.iter().all(|&color| base.material[color as usize] == Self::count_material(board, color, params)
+            && PIECE_TYPES.iter().enumerate().all(|(i, &piece)| base.pst[color as usize][i] == Self::evaluate_piece_square(board, piece, color, params))),
+            "incremental material or piece square sums out of sync");
+
+        terms.add("Material", base.material[Player::White as usize], base.material[Player::Black as usize]);
+
+        for (i, name) in ["PST pawns", "PST knights", "PST bishops", "PST rooks", "PST queens", "PST king"].into_iter().enumerate() {
+            terms.add(name,
+                      base.pst[Player::White as usize][i] * params.piece_square_weight,
+                      base.pst[Player::Black as usize][i] * params.piece_square_weight);
+        }
+
+        let pawns = self.pawn_table.probe(board, params);
+        terms.add("Pawn structure", pawns.scores[Player::White as usize], pawns.scores[Player::Black as usize]);
+
+        let white = evaluate_pieces(board, Player::White, params);
+        let black = evaluate_pieces(board, Player::Black, params);
+        terms.add("Mobility knights", white.knight_mobility, black.knight_mobility);
+        terms.add("Mobility bishops", white.bishop_mobility, black.bishop_mobility);
+        terms.add("Mobility rooks", white.rook_mobility, black.rook_mobility);
+        terms.add("Mobility queens", white.queen_mobility, black.queen_mobility);
+        terms.add("Bishop pair", white.bishop_pair, black.bishop_pair);
+        terms.add("Rook files", white.rook_files, black.rook_files);
+        terms.add("Rook on 7th", white.rook_seventh, black.rook_seventh);
+        terms.add("Knight outposts", white.knight_outposts, black.knight_outposts);
+        terms.add("Trapped pieces", white.trapped_pieces, black.trapped_pieces);
 
-        material += board.count_piece(color, PieceType::P) as i32 * PAWN_VALUE;
-        material += board.count_piece(color, PieceType::N) as i32 * KNIGHT_VALUE;
-        material += board.count_piece(color, PieceType::B) as i32 * BISHOP_VALUE;
-        material += board.count_piece(color, PieceType::R) as i32 * ROOK_VALUE;
-        material += board.count_piece(color, PieceType::Q) as i32 * QUEEN_VALUE;
+        terms.add("King safety", evaluate_king_safety(board, Player::White, params), evaluate_king_safety(board, Player::Black, params));
+
+        terms.total
+    }
+    fn count_material(board: &Board, color: Player, params: &EvalParams) -> Score {
+        let mut material = Score::ZERO;
+
+        material += params.pawn_value * board.count_piece(color, PieceType::P) as i32;
+        material += params.knight_value * board.count_piece(color, PieceType::N) as i32;
+        material += params.bishop_value * board.count_piece(color, PieceType::B) as i32;
+        material += params.rook_value * board.count_piece(color, PieceType::R) as i32;
+        material += params.queen_value * board.count_piece(color, PieceType::Q) as i32;
 
         material
     }
-    pub fn capture_value(board: &Board, m: BitMove) -> i32 {
-        ClassicEvaluator::piece_value(board.piece_at_sq(m.get_dest()).type_of())
+    /// Goes from `MAX_PHASE` with all the pieces on the board down to 0 with only pawns and kings
+    pub fn game_phase(&self, board: &Board) -> i32 {
+        let mut phase = 0;
+        for color in [Player::White, Player::Black] {
+            phase += board.count_piece(color, PieceType::N) as i32 * self.params.knight_phase;
+            phase += board.count_piece(color, PieceType::B) as i32 * self.params.bishop_phase;
+            phase += board.count_piece(color, PieceType::R) as i32 * self.params.rook_phase;
+            phase += board.count_piece(color, PieceType::Q) as i32 * self.params.queen_phase;
+        }
+        phase.min(MAX_PHASE)
     }
     // pub fn capture_value(game: &Game) -> i32 {
     //     let mut capture_score = 0;
@@ -72,41 +148,10 @@ impl ClassicEvaluator {
     // 
     //     capture_score
     // }
-    pub fn piece_value(piece: PieceType) -> i32 {
-        match piece {
-            PieceType::P => PAWN_VALUE,
-            PieceType::N => KNIGHT_VALUE,
-            PieceType::B => BISHOP_VALUE,
-            PieceType::R => ROOK_VALUE,
-            PieceType::Q => QUEEN_VALUE,
-            _ => 0
+    pub fn evaluate_piece_square(board: &Board, piece: PieceType, color: Player, params: &EvalParams) -> Score {
+        if piece == PieceType::K {
+            return PieceSquareTables::eval_king(board.king_sq(color), color, params);
         }
+        PieceSquareTables::eval_piece(&board.piece_bb(color, piece), piece, color, params)
     }
-    pub fn evaluate_piece_square(board: &Board, endgame_weight: f32, color: Player) -> f32 {
-        let pawns = board.piece_bb(color, PieceType::P);
-
-        let knights = board.piece_bb(color, PieceType::N);
-
-        let bishops = board.piece_bb(color, PieceType::B);
-
-        let rooks = board.piece_bb(color, PieceType::R);
-
-        let queens = board.piece_bb(color, PieceType::Q);
-
-        let king = board.king_sq(color);
-
-        let mut eval: f32 = 0.0;
-        eval += PieceSquareTables::eval_piece(&pawns, PieceType::P, color, endgame_weight);
-        eval += PieceSquareTables::eval_piece(&knights, PieceType::N, color, endgame_weight);
-        eval += PieceSquareTables::eval_piece(&bishops, PieceType::B, color, endgame_weight);
-        eval += PieceSquareTables::eval_piece(&rooks, PieceType::R, color, endgame_weight);
-        eval += PieceSquareTables::eval_piece(&queens, PieceType::Q, color, endgame_weight);
-        eval += PieceSquareTables::eval_king(king, color, endgame_weight);
-
-        eval
-    }
-    pub fn endgame_phase_weight(material_count_without_pawns: i32) -> f32 {
-        const MULTIPLIER: f32 = 1f32 / ENDGAME_MATERIAL_START as f32;
-        1f32 - if 1f32 > MULTIPLIER * material_count_without_pawns as f32 { MULTIPLIER * material_count_without_pawns as f32 } else { 1f32 }
-    }
-}
\ No newline at end of file
+}
@@ -0,0 +1,111 @@
+use pleco::{Board, PieceType, Player, SQ};
+use crate::core::eval::params::EvalParams;
+use crate::core::eval::piece_square_tables::PieceSquareTables;
+use crate::core::eval::score::Score;
+
+pub const PIECE_TYPES: [PieceType; 6] = [PieceType::P, PieceType::N, PieceType::B, PieceType::R, PieceType::Q, PieceType::K];
+pub const COLORS: [Player; 2] = [Player::White, Player::Black];
+
+/// Bitboard of every (color, piece type) pair, white pieces first, each color in `PIECE_TYPES` order
+pub fn piece_boards(board: &Board) -> [u64; 12] {
+    let mut boards = [0; 12];
+    for (c, &color) in COLORS.iter().enumerate() {
+        for (p, &piece) in PIECE_TYPES.iter().enumerate() {
+            boards[c * 6 + p] = board.piece_bb(color, piece).0;
+        }
+    }
+    boards
+}
+
+/// Material and piece square sums of a position, with the pieces they were computed for
+#[derive(Copy, Clone)]
+pub struct MaterialPst {
+    pieces: [u64; 12],
+    /// Indexed by `Player as usize`
+    pub material: [Score; 2],
+    /// Unweighted piece square values, indexed by `Player as usize` then by `PIECE_TYPES` position
+    pub pst: [[Score; 6]; 2],
+}
+
+impl MaterialPst {
+    fn compute(pieces: [u64; 12], params: &EvalParams) -> Self {
+        let mut sums = Self {
+            pieces: [0; 12],
+            material: [Score::ZERO; 2],
+            pst: [[Score::ZERO; 6]; 2],
+        };
+        sums.update(pieces, params);
+        sums
+    }
+    /// Moves the sums to `pieces`, only looking at the squares that changed
+    fn update(&mut self, pieces: [u64; 12], params: &EvalParams) {
+        for i in 0..12 {
+            let (color, piece) = (COLORS[i / 6], PIECE_TYPES[i % 6]);
+            let value = params.piece_value(piece);
+            let mut removed = self.pieces[i] & !pieces[i];
+            while removed != 0 {
+                let sq = SQ(removed.trailing_zeros() as u8);
+                removed &= removed - 1;
+                self.material[i / 6] -= value;
+                self.pst[i / 6][i % 6] -= PieceSquareTables::get_value_square(sq, piece, color, params);
+            }
+            let mut added = pieces[i] & !self.pieces[i];
+            while added != 0 {
+                let sq = SQ(added.trailing_zeros() as u8);
+                added &= added - 1;
+                self.material[i / 6] += value;
+                self.pst[i / 6][i % 6] += PieceSquareTables::get_value_square(sq, piece, color, params);
+            }
+        }
+        self.pieces = pieces;
+    }
+}
+
+/// Stack of `MaterialPst` following the moves made in the search, so each node only updates
+/// the squares its move changed instead of going through every piece.
+/// A position reached without `make_move` is detected and computed from scratch.
+pub struct IncrementalEval {
+    stack: Vec<MaterialPst>,
+}
+
+impl IncrementalEval {
+    pub fn new() -> Self {
+        Self {
+            stack: Vec::with_capacity(u8::MAX as usize + 1),
+        }
+    }
+    /// Must be called when the parameters change, as the stored sums depend on them
+    pub fn clear(&mut self) {
+        self.stack.clear();
+    }
+    /// Sums for `board`
+    pub fn current(&mut self, board: &Board, params: &EvalParams) -> MaterialPst {
+        let pieces = piece_boards(board);
+        match self.stack.last_mut() {
+            Some(top) if top.pieces == pieces => *top,
+            Some(top) => {
+                *top = MaterialPst::compute(pieces, params);
+                *top
+            }
+            None => {
+                self.stack.push(MaterialPst::compute(pieces, params));
+                self.stack[0]
+            }
+        }
+    }
+    pub fn make_move(&mut self, board: &Board, params: &EvalParams) {
+        let pieces = piece_boards(board);
+        let next = match self.stack.last() {
+            Some(&top) => {
+                let mut next = top;
+                next.update(pieces, params);
+                next
+            }
+            None => MaterialPst::compute(pieces, params),
+        };
+        self.stack.push(next);
+    }
+    pub fn unmake_move(&mut self) {
+        self.stack.pop();
+    }
+}
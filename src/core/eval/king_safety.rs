@@ -0,0 +1,107 @@
+use pleco::{Board, PieceType, Player, SQ};
+use pleco::helper::prelude::{bishop_moves, king_moves, knight_moves, queen_moves, rook_moves};
+use crate::core::eval::pawns::{file_mask, pawn_attacks, relative_rank};
+use crate::core::eval::params::EvalParams;
+use crate::core::eval::score::Score;
+
+/// King safety of `color`, negative when the king is in danger.
+/// Only the middlegame half is set, so the whole term fades out as pieces come off.
+pub fn evaluate_king_safety(board: &Board, color: Player, params: &EvalParams) -> Score {
+    let king = board.king_sq(color);
+    let occupied = board.occupied();
+    let own_pawns = board.piece_bb(color, PieceType::P).0;
+    let enemy_pawns = board.piece_bb(!color, PieceType::P).0;
+    let enemy_pieces = board.get_occupied_player(!color).0;
+
+    // King zone: the king square, its neighbours and the rank in front of them
+    let around = king_moves(king).0 | king.to_bb().0;
+    let zone = around | if color == Player::White { around << 8 } else { around >> 8 };
+
+    let mut attackers = 0;
+    let mut units = 0;
+    let mut enemy_attacks = [0u64; 4];
+    for (i, (piece, weight)) in [(PieceType::N, params.knight_attack_units), (PieceType::B, params.bishop_attack_units),
+                                 (PieceType::R, params.rook_attack_units), (PieceType::Q, params.queen_attack_units)].into_iter().enumerate() {
+        for sq in board.piece_bb(!color, piece) {
+            let attacks = piece_attacks(piece, sq, occupied.0);
+            enemy_attacks[i] |= attacks;
+            let zone_attacks = (attacks & zone).count_ones() as i32;
+            if zone_attacks > 0 {
+                attackers += 1;
+                units += weight * zone_attacks;
+            }
+        }
+    }
+
+    // Safe checks: check squares reachable by an enemy piece that we don't defend
+    let defended = own_attacks(board, color);
+    let safe = !defended & !enemy_pieces;
+    let knight_checks = knight_moves(king).0 & enemy_attacks[0] & safe;
+    let bishop_checks = bishop_moves(occupied, king).0 & enemy_attacks[1] & safe;
+    let rook_checks = rook_moves(occupied, king).0 & enemy_attacks[2] & safe;
+    let queen_checks = queen_moves(occupied, king).0 & enemy_attacks[3] & safe;
+    units += params.knight_safe_check_units * knight_checks.count_ones() as i32;
+    units += params.bishop_safe_check_units * bishop_checks.count_ones() as i32;
+    units += params.rook_safe_check_units * rook_checks.count_ones() as i32;
+    units += params.queen_safe_check_units * queen_checks.count_ones() as i32;
+
+    // A lone attacker is rarely dangerous unless it is the queen
+    let mut danger = 0;
+    if attackers >= 2 || (attackers >= 1 && board.count_piece(!color, PieceType::Q) > 0) {
+        let units = units.min(params.max_attack_units);
+        danger = units * units / params.king_danger_divisor.max(1);
+    }
+
+    let mut shelter = 0;
+    let king_file = king.0 % 8;
+    for file in king_file.saturating_sub(1)..=(king_file + 1).min(7) {
+        let mask = file_mask(file);
+        let own_on_file = own_pawns & mask;
+        let enemy_on_file = enemy_pawns & mask;
+
+        // Closest own pawn to our back rank, and closest storming pawn to our king
+        let shield_rank = closest_pawn_rank(own_on_file, color);
+        let storm_rank = closest_pawn_rank(enemy_on_file, color);
+        shelter += params.pawn_shield[shield_rank];
+        shelter += params.pawn_storm[storm_rank];
+
+        if own_on_file == 0 {
+            shelter += if enemy_on_file == 0 { params.open_file_near_king } else { params.semi_open_file_near_king };
+        }
+    }
+
+    Score::new(shelter - danger, 0)
+}
+
+fn piece_attacks(piece: PieceType, sq: SQ, occupied: u64) -> u64 {
+    let occupied = pleco::BitBoard(occupied);
+    match piece {
+        PieceType::N => knight_moves(sq).0,
+        PieceType::B => bishop_moves(occupied, sq).0,
+        PieceType::R => rook_moves(occupied, sq).0,
+        PieceType::Q => queen_moves(occupied, sq).0,
+        PieceType::K => king_moves(sq).0,
+        _ => 0,
+    }
+}
+
+/// Every square attacked by `color`
+fn own_attacks(board: &Board, color: Player) -> u64 {
+    let occupied = board.occupied().0;
+    let mut attacks = pawn_attacks(color, board.piece_bb(color, PieceType::P).0);
+    for piece in [PieceType::N, PieceType::B, PieceType::R, PieceType::Q, PieceType::K] {
+        for sq in board.piece_bb(color, piece) {
+            attacks |= piece_attacks(piece, sq, occupied);
+        }
+    }
+    attacks
+}
+
+/// Rank relative to `color` of the pawn closest to `color`'s back rank, 0 if there is none
+fn closest_pawn_rank(pawns: u64, color: Player) -> usize {
+    if pawns == 0 {
+        return 0;
+    }
+    let sq = if color == Player::White { pawns.trailing_zeros() } else { 63 - pawns.leading_zeros() };
+    relative_rank(color, sq as u8)
+}
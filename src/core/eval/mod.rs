@@ -1,9 +1,35 @@
-use pleco::{Board, Player};
+use pleco::{BitMove, Board, PieceType, Player};
 use crate::core::eval::evaluation::ClassicEvaluator;
+use crate::core::eval::params::DEFAULT_PARAMS;
 
 pub mod evaluation;
+pub mod score;
+pub mod pawns;
+pub mod pieces;
+pub mod king_safety;
+pub mod endgames;
+pub mod params;
+pub mod trace;
+pub mod nnue;
+pub mod incremental;
+pub mod eval_cache;
 mod piece_square_tables;
+#[cfg(test)]
+mod symmetry_tests;
 
 pub trait Evaluator {
     fn evaluate_board(&mut self, board: &Board) -> i32;
+    /// Called by the search right after a move is made on `board`, so evaluators keeping
+    /// incremental state can update it
+    fn make_move(&mut self, _board: &Board) {}
+    /// Called by the search right after the last move made is undone
+    fn unmake_move(&mut self) {}
+    /// Middlegame value of a piece, used to order captures
+    fn piece_value(&self, piece: PieceType) -> i32 {
+        DEFAULT_PARAMS.piece_value(piece).mg()
+    }
+    /// Value of the piece taken by `m`, 0 for a quiet move
+    fn capture_value(&self, board: &Board, m: BitMove) -> i32 {
+        self.piece_value(board.piece_at_sq(m.get_dest()).type_of())
+    }
 }
@@ -0,0 +1,340 @@
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use pleco::{Board, Player};
+use crate::core::eval::Evaluator;
+use crate::core::eval::incremental::piece_boards;
+
+/// One input per (color, piece type, square)
+pub const INPUT_SIZE: usize = 768;
+/// Quantization of the hidden layer, whose activations are clipped to `[0, QA]`
+pub const QA: i32 = 255;
+/// Quantization of the output weights
+pub const QB: i32 = 64;
+/// Converts the network output to centipawns
+pub const EVAL_SCALE: i32 = 400;
+const MAGIC: &[u8; 4] = b"CNN1";
+
+/// `piece` is the position of the piece type in `PIECE_TYPES`, so the inputs follow `piece_boards`
+pub fn feature_index(color: Player, piece: usize, sq: usize) -> usize {
+    color as usize * 384 + piece * 64 + sq
+}
+
+/// A 768 -> N -> 1 network with a clipped ReLU hidden layer, scoring positions from white's point of view.
+///
+/// File layout (little endian): the `CNN1` magic, the hidden size as an u32, the feature weights
+/// as i16 (768 rows of N, in `feature_index` order), the hidden biases and output weights as i16,
+/// and the output bias as an i32.
+pub struct NnueNetwork {
+    pub hidden_size: usize,
+    pub feature_weights: Vec<i16>,
+    pub hidden_bias: Vec<i16>,
+    pub output_weights: Vec<i16>,
+    pub output_bias: i32,
+}
+
+impl NnueNetwork {
+    pub fn load(path: &str) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        if data.len() < 8 || &data[0..4] != MAGIC {
+            return Err(invalid("not a network file"));
+        }
+        let hidden_size = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        let i16_count = INPUT_SIZE * hidden_size + 2 * hidden_size;
+        if hidden_size == 0 || data.len() != 8 + 2 * i16_count + 4 {
+            return Err(invalid("unexpected network size"));
+        }
+
+        let mut values = data[8..8 + 2 * i16_count].chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]));
+        let feature_weights: Vec<i16> = values.by_ref().take(INPUT_SIZE * hidden_size).collect();
+        let hidden_bias: Vec<i16> = values.by_ref().take(hidden_size).collect();
+        let output_weights: Vec<i16> = values.take(hidden_size).collect();
+        let output_bias = i32::from_le_bytes(data[data.len() - 4..].try_into().unwrap());
+        Ok(Self { hidden_size, feature_weights, hidden_bias, output_weights, output_bias })
+    }
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut data = Vec::with_capacity(8 + 2 * (INPUT_SIZE + 2) * self.hidden_size + 4);
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&(self.hidden_size as u32).to_le_bytes());
+        for v in self.feature_weights.iter().chain(&self.hidden_bias).chain(&self.output_weights) {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend_from_slice(&self.output_bias.to_le_bytes());
+        fs::write(path, data)
+    }
+    fn feature_weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden_size..(feature + 1) * self.hidden_size]
+    }
+    /// Evaluation in centipawns from white's point of view of the position with the given inputs
+    /// active, with the same i16 sums and forward pass as `NnueEvaluator`
+    pub fn evaluate_features(&self, features: impl IntoIterator<Item = usize>) -> i32 {
+        let mut accumulator = self.hidden_bias.clone();
+        for feature in features {
+            add_weights(&mut accumulator, self.feature_weights(feature));
+        }
+        self.output(&accumulator)
+    }
+    /// Evaluation in centipawns from the hidden layer sums
+    fn output(&self, accumulator: &[i16]) -> i32 {
+        let sum = forward(accumulator, &self.output_weights);
+        (sum + self.output_bias) * EVAL_SCALE / (QA * QB)
+    }
+}
+
+fn forward(accumulator: &[i16], weights: &[i16]) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if accumulator.len() % 16 == 0 && is_x86_feature_detected!("avx2") {
+            return unsafe { forward_avx2(accumulator, weights) };
+        }
+    }
+    forward_scalar(accumulator, weights)
+}
+
+fn forward_scalar(accumulator: &[i16], weights: &[i16]) -> i32 {
+    accumulator.iter().zip(weights).map(|(&a, &w)| (a as i32).clamp(0, QA) * w as i32).sum()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn forward_avx2(accumulator: &[i16], weights: &[i16]) -> i32 {
+    use std::arch::x86_64::*;
+    let zero = _mm256_setzero_si256();
+    let qa = _mm256_set1_epi16(QA as i16);
+    let mut sum = _mm256_setzero_si256();
+    for i in (0..accumulator.len()).step_by(16) {
+        let a = _mm256_loadu_si256(accumulator.as_ptr().add(i) as *const __m256i);
+        let a = _mm256_min_epi16(_mm256_max_epi16(a, zero), qa);
+        let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+        sum = _mm256_add_epi32(sum, _mm256_madd_epi16(a, w));
+    }
+    let sum = _mm_add_epi32(_mm256_castsi256_si128(sum), _mm256_extracti128_si256(sum, 1));
+    let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b01_00_11_10));
+    let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b10_11_00_01));
+    _mm_cvtsi128_si32(sum)
+}
+
+/// Hidden layer sums of a position, with the pieces they were computed for
+#[derive(Clone)]
+struct Accumulator {
+    pieces: [u64; 12],
+    values: Vec<i16>,
+}
+
+impl Accumulator {
+    fn refresh(&mut self, network: &NnueNetwork, pieces: [u64; 12]) {
+        self.values.clear();
+        self.values.extend_from_slice(&network.hidden_bias);
+        for (i, &bb) in pieces.iter().enumerate() {
+            let mut bb = bb;
+            while bb != 0 {
+                let sq = bb.trailing_zeros() as usize;
+                bb &= bb - 1;
+                add_weights(&mut self.values, network.feature_weights(i * 64 + sq));
+            }
+        }
+        self.pieces = pieces;
+    }
+    /// Moves `self` to `pieces` starting from `previous`, only touching the features that changed
+    fn update_from(&mut self, previous: &Accumulator, network: &NnueNetwork, pieces: [u64; 12]) {
+        self.values.clear();
+        self.values.extend_from_slice(&previous.values);
+        for i in 0..12 {
+            let mut removed = previous.pieces[i] & !pieces[i];
+            while removed != 0 {
+                let sq = removed.trailing_zeros() as usize;
+                removed &= removed - 1;
+                sub_weights(&mut self.values, network.feature_weights(i * 64 + sq));
+            }
+            let mut added = pieces[i] & !previous.pieces[i];
+            while added != 0 {
+                let sq = added.trailing_zeros() as usize;
+                added &= added - 1;
+                add_weights(&mut self.values, network.feature_weights(i * 64 + sq));
+            }
+        }
+        self.pieces = pieces;
+    }
+}
+
+// Plain loops over i16 slices, which the compiler vectorizes on its own
+fn add_weights(values: &mut [i16], weights: &[i16]) {
+    for (v, &w) in values.iter_mut().zip(weights) {
+        *v = v.wrapping_add(w);
+    }
+}
+fn sub_weights(values: &mut [i16], weights: &[i16]) {
+    for (v, &w) in values.iter_mut().zip(weights) {
+        *v = v.wrapping_sub(w);
+    }
+}
+
+/// Evaluates positions with an `NnueNetwork`.
+/// Keeps a stack of accumulators following the moves made in the search, so each node only
+/// updates the few inputs its move changed. Positions reached without going through
+/// `make_move` are detected and fully recomputed.
+pub struct NnueEvaluator {
+    network: Arc<NnueNetwork>,
+    stack: Vec<Accumulator>,
+    len: usize,
+}
+
+impl NnueEvaluator {
+    pub fn new(network: Arc<NnueNetwork>) -> Self {
+        Self {
+            network,
+            stack: vec![],
+            len: 0,
+        }
+    }
+    fn push_slot(&mut self) {
+        if self.stack.len() == self.len {
+            self.stack.push(Accumulator { pieces: [0; 12], values: Vec::with_capacity(self.network.hidden_size) });
+        }
+        self.len += 1;
+    }
+}
+
+impl Evaluator for NnueEvaluator {
+    fn evaluate_board(&mut self, board: &Board) -> i32 {
+        let pieces = piece_boards(board);
+        if self.len == 0 {
+            self.push_slot();
+            self.stack[0].refresh(&self.network, pieces);
+        } else if self.stack[self.len - 1].pieces != pieces {
+            self.stack[self.len - 1].refresh(&self.network, pieces);
+        }
+        let eval = self.network.output(&self.stack[self.len - 1].values);
+        if board.turn() == Player::White { eval } else { -eval }
+    }
+    fn make_move(&mut self, board: &Board) {
+        let pieces = piece_boards(board);
+        if self.len == 0 {
+            self.push_slot();
+            self.stack[0].refresh(&self.network, pieces);
+            return;
+        }
+        self.push_slot();
+        let (done, rest) = self.stack.split_at_mut(self.len - 1);
+        rest[0].update_from(&done[self.len - 2], &self.network, pieces);
+    }
+    fn unmake_move(&mut self) {
+        self.len = self.len.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    const HIDDEN_SIZE: usize = 32;
+
+    /// Large enough weights that the hidden sums often leave `[0, QA]`
+    fn random_network(seed: u64) -> NnueNetwork {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        NnueNetwork {
+            hidden_size: HIDDEN_SIZE,
+            feature_weights: (0..INPUT_SIZE * HIDDEN_SIZE).map(|_| rng.random_range(-128..128)).collect(),
+            hidden_bias: (0..HIDDEN_SIZE).map(|_| rng.random_range(-256..256)).collect(),
+            output_weights: (0..HIDDEN_SIZE).map(|_| rng.random_range(-64..64)).collect(),
+            output_bias: rng.random_range(-10_000..10_000),
+        }
+    }
+
+    fn full_refresh(network: &NnueNetwork, board: &Board) -> i32 {
+        let pieces = piece_boards(board);
+        let features = (0..12).flat_map(|i| (0..64).filter(move |&sq| pieces[i] >> sq & 1 != 0).map(move |sq| i * 64 + sq));
+        let eval = network.evaluate_features(features);
+        if board.turn() == Player::White { eval } else { -eval }
+    }
+
+    fn square(name: &str) -> u8 {
+        let b = name.as_bytes();
+        (b[0] - b'a') + 8 * (b[1] - b'1')
+    }
+
+    fn play(board: &mut Board, uci: &str) {
+        let (from, to) = (square(&uci[0..2]), square(&uci[2..4]));
+        let m = board.generate_moves().iter().copied().find(|m| {
+            if m.is_castle() {
+                // Castling may not be encoded as king to destination, so only compare the side
+                m.get_src().0 == from && (m.get_dest().0 > from) == (to > from)
+            } else {
+                m.stringify() == uci
+            }
+        });
+        board.apply_move(m.unwrap_or_else(|| panic!("{} should be legal", uci)));
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("nnue_test_{}_{}.bin", std::process::id(), name));
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn incremental_updates_match_a_full_refresh() {
+        let network = Arc::new(random_network(1));
+        let mut evaluator = NnueEvaluator::new(network.clone());
+        let mut board = Board::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        // En passant, castling on both sides, a capturing promotion and the capture of the new queen
+        let moves = ["e5d6", "e8g8", "b7a8q", "f8a8", "e1c1", "a8a2", "d6d7"];
+
+        assert_eq!(evaluator.evaluate_board(&board), full_refresh(&network, &board));
+        for m in moves {
+            play(&mut board, m);
+            evaluator.make_move(&board);
+            assert_eq!(evaluator.evaluate_board(&board), full_refresh(&network, &board), "after {}", m);
+        }
+        for m in moves.iter().rev() {
+            board.undo_move();
+            evaluator.unmake_move();
+            assert_eq!(evaluator.evaluate_board(&board), full_refresh(&network, &board), "before {}", m);
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn avx2_forward_matches_scalar() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+        let mut rng = SmallRng::seed_from_u64(2);
+        for _ in 0..100 {
+            let mut accumulator: Vec<i16> = (0..64).map(|_| rng.random_range(-1000..1000)).collect();
+            accumulator[0] = i16::MIN;
+            accumulator[1] = i16::MAX;
+            let weights: Vec<i16> = (0..64).map(|_| rng.random()).collect();
+            assert_eq!(unsafe { forward_avx2(&accumulator, &weights) }, forward_scalar(&accumulator, &weights));
+        }
+    }
+
+    #[test]
+    fn saved_networks_load_back() {
+        let network = random_network(3);
+        let path = temp_path("round_trip");
+        network.save(&path).unwrap();
+        let loaded = NnueNetwork::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.hidden_size, network.hidden_size);
+        assert_eq!(loaded.feature_weights, network.feature_weights);
+        assert_eq!(loaded.hidden_bias, network.hidden_bias);
+        assert_eq!(loaded.output_weights, network.output_weights);
+        assert_eq!(loaded.output_bias, network.output_bias);
+    }
+
+    #[test]
+    fn truncated_networks_are_rejected() {
+        let path = temp_path("truncated");
+        random_network(4).save(&path).unwrap();
+        let data = fs::read(&path).unwrap();
+        for len in [data.len() - 1, data.len() - 4, 8, 3] {
+            fs::write(&path, &data[..len]).unwrap();
+            assert!(NnueNetwork::load(&path).is_err(), "{} bytes should be rejected", len);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+use std::fs;
+use std::io;
+use std::sync::LazyLock;
+use pleco::PieceType;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::Error;
+use crate::core::eval::evaluation::{BISHOP_VALUE, KNIGHT_VALUE, PAWN_VALUE, QUEEN_VALUE, ROOK_VALUE};
+use crate::core::eval::piece_square_tables::{BISHOP_TABLE, KING_END_TABLE, KING_MID_TABLE, KNIGHT_TABLE, PAWN_END_TABLE, PAWN_TABLE, QUEEN_TABLE, ROOK_TABLE};
+use crate::core::eval::score::Score;
+
+/// Shared copy of the defaults, for evaluators without parameters of their own
+pub static DEFAULT_PARAMS: LazyLock<EvalParams> = LazyLock::new(EvalParams::default);
+
+/// Every weight and table used by `ClassicEvaluator`.
+/// The default matches the built-in values; a JSON file only needs the fields it changes.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvalParams {
+    pub pawn_value: Score,
+    pub knight_value: Score,
+    pub bishop_value: Score,
+    pub rook_value: Score,
+    pub queen_value: Score,
+
+    pub knight_phase: i32,
+    pub bishop_phase: i32,
+    pub rook_phase: i32,
+    pub queen_phase: i32,
+
+    /// How much the piece square tables weigh compared to a centipawn of material
+    pub piece_square_weight: i32,
+    /// Piece square tables from white's point of view, a8 first
+    #[serde(with = "table")]
+    pub pawn_table: [Score; 64],
+    #[serde(with = "table")]
+    pub knight_table: [Score; 64],
+    #[serde(with = "table")]
+    pub bishop_table: [Score; 64],
+    #[serde(with = "table")]
+    pub rook_table: [Score; 64],
+    #[serde(with = "table")]
+    pub queen_table: [Score; 64],
+    #[serde(with = "table")]
+    pub king_table: [Score; 64],
+
+    /// Indexed by the rank of the pawn from its own side, rank 1 being 0
+    pub passed_pawn_bonus: [Score; 8],
+    pub candidate_pawn_bonus: [Score; 8],
+    pub connected_pawn_bonus: [Score; 8],
+    pub isolated_pawn_penalty: Score,
+    pub doubled_pawn_penalty: Score,
+    pub backward_pawn_penalty: Score,
+
+    /// Bonus per reachable square, relative to the average mobility of the piece
+    pub knight_mobility: Score,
+    pub bishop_mobility: Score,
+    pub rook_mobility: Score,
+    pub queen_mobility: Score,
+    pub knight_average_mobility: i32,
+    pub bishop_average_mobility: i32,
+    pub rook_average_mobility: i32,
+    pub queen_average_mobility: i32,
+    pub bishop_pair: Score,
+    pub rook_open_file: Score,
+    pub rook_semi_open_file: Score,
+    pub rook_on_seventh: Score,
+    pub knight_outpost: Score,
+    pub trapped_bishop: Score,
+    pub trapped_rook: Score,
+
+    /// Attack units for each piece attacking the king zone, per attacked zone square
+    pub knight_attack_units: i32,
+    pub bishop_attack_units: i32,
+    pub rook_attack_units: i32,
+    pub queen_attack_units: i32,
+    /// Attack units for each square an enemy piece could safely give check from
+    pub knight_safe_check_units: i32,
+    pub bishop_safe_check_units: i32,
+    pub rook_safe_check_units: i32,
+    pub queen_safe_check_units: i32,
+    pub max_attack_units: i32,
+    /// The king danger is `units * units / king_danger_divisor`
+    pub king_danger_divisor: i32,
+    /// Indexed by the rank of the shield pawn relative to its side, 0 meaning there is no pawn on the file
+    pub pawn_shield: [i32; 8],
+    /// Indexed by the rank of the storming pawn relative to the defending side, 0 meaning there is no pawn
+    pub pawn_storm: [i32; 8],
+    pub semi_open_file_near_king: i32,
+    pub open_file_near_king: i32,
+
+    /// Out of `SCALE_NORMAL`
+    pub scale_opposite_bishops: i32,
+    pub scale_opposite_bishops_with_pieces: i32,
+    pub scale_no_pawns_small_advantage: i32,
+    pub losing_king_center_distance_weight: i32,
+    pub kings_proximity_weight: i32,
+    pub kbnk_corner_weight: i32,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self {
+            pawn_value: Score::new(PAWN_VALUE, PAWN_VALUE),
+            knight_value: Score::new(KNIGHT_VALUE, KNIGHT_VALUE),
+            bishop_value: Score::new(BISHOP_VALUE, BISHOP_VALUE),
+            rook_value: Score::new(ROOK_VALUE, ROOK_VALUE),
+            queen_value: Score::new(QUEEN_VALUE, QUEEN_VALUE),
+
+            knight_phase: 1,
+            bishop_phase: 1,
+            rook_phase: 2,
+            queen_phase: 4,
+
+            piece_square_weight: 2,
+            pawn_table: tapered_table(&PAWN_TABLE, &PAWN_END_TABLE),
+            knight_table: tapered_table(&KNIGHT_TABLE, &KNIGHT_TABLE),
+            bishop_table: tapered_table(&BISHOP_TABLE, &BISHOP_TABLE),
+            rook_table: tapered_table(&ROOK_TABLE, &ROOK_TABLE),
+            queen_table: tapered_table(&QUEEN_TABLE, &QUEEN_TABLE),
+            king_table: tapered_table(&KING_MID_TABLE, &KING_END_TABLE),
+
+            passed_pawn_bonus: [
+                Score::ZERO, Score::new(5, 10), Score::new(10, 15), Score::new(15, 25),
+                Score::new(30, 50), Score::new(55, 90), Score::new(90, 140), Score::ZERO,
+            ],
+            candidate_pawn_bonus: [
+                Score::ZERO, Score::new(2, 5), Score::new(4, 8), Score::new(8, 12),
+                Score::new(12, 20), Score::new(20, 35), Score::ZERO, Score::ZERO,
+            ],
+            connected_pawn_bonus: [
+                Score::ZERO, Score::new(3, 2), Score::new(5, 4), Score::new(8, 6),
+                Score::new(14, 12), Score::new(25, 25), Score::new(40, 45), Score::ZERO,
+            ],
+            isolated_pawn_penalty: Score::new(-10, -15),
+            doubled_pawn_penalty: Score::new(-10, -25),
+            backward_pawn_penalty: Score::new(-8, -12),
+
+            knight_mobility: Score::new(4, 4),
+            bishop_mobility: Score::new(5, 5),
+            rook_mobility: Score::new(2, 4),
+            queen_mobility: Score::new(1, 2),
+            knight_average_mobility: 4,
+            bishop_average_mobility: 6,
+            rook_average_mobility: 7,
+            queen_average_mobility: 13,
+            bishop_pair: Score::new(30, 50),
+            rook_open_file: Score::new(25, 10),
+            rook_semi_open_file: Score::new(12, 6),
+            rook_on_seventh: Score::new(10, 20),
+            knight_outpost: Score::new(20, 10),
+            trapped_bishop: Score::new(-100, -80),
+            trapped_rook: Score::new(-45, -5),
+
+            knight_attack_units: 2,
+            bishop_attack_units: 2,
+            rook_attack_units: 3,
+            queen_attack_units: 5,
+            knight_safe_check_units: 6,
+            bishop_safe_check_units: 5,
+            rook_safe_check_units: 8,
+            queen_safe_check_units: 7,
+            max_attack_units: 100,
+            king_danger_divisor: 8,
+            pawn_shield: [-25, 15, 8, 0, 0, 0, 0, 0],
+            pawn_storm: [0, 0, -30, -20, -10, 0, 0, 0],
+            semi_open_file_near_king: -12,
+            open_file_near_king: -25,
+
+            scale_opposite_bishops: 16,
+            scale_opposite_bishops_with_pieces: 40,
+            scale_no_pawns_small_advantage: 8,
+            losing_king_center_distance_weight: 10,
+            kings_proximity_weight: 4,
+            kbnk_corner_weight: 20,
+        }
+    }
+}
+
+impl EvalParams {
+    pub fn load(path: &str) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, json)
+    }
+    pub fn piece_value(&self, piece: PieceType) -> Score {
+        match piece {
+            PieceType::P => self.pawn_value,
+            PieceType::N => self.knight_value,
+            PieceType::B => self.bishop_value,
+            PieceType::R => self.rook_value,
+            PieceType::Q => self.queen_value,
+            _ => Score::ZERO,
+        }
+    }
+}
+
+fn tapered_table(mg: &[i32; 64], eg: &[i32; 64]) -> [Score; 64] {
+    let mut table = [Score::ZERO; 64];
+    for i in 0..64 {
+        table[i] = Score::new(mg[i], eg[i]);
+    }
+    table
+}
+
+/// serde only derives arrays up to 32 elements, so the tables go through a `Vec`
+mod table {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(table: &[Score; 64], serializer: S) -> Result<S::Ok, S::Error> {
+        table.as_slice().serialize(serializer)
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[Score; 64], D::Error> {
+        let values = Vec::<Score>::deserialize(deserializer)?;
+        let len = values.len();
+        values.try_into().map_err(|_| D::Error::custom(format!("expected 64 values, found {}", len)))
+    }
+}
@@ -0,0 +1,149 @@
+use pleco::{Board, PieceType, Player};
+use crate::core::eval::params::EvalParams;
+use crate::core::eval::score::Score;
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+const FILE_H: u64 = FILE_A << 7;
+
+const PAWN_TABLE_ENTRIES: usize = 1 << 14;
+
+#[derive(Copy, Clone, Default)]
+pub struct PawnEntry {
+    pub key: u64,
+    pub valid: bool,
+    /// Pawn structure score of each player, indexed by `Player as usize`
+    pub scores: [Score; 2],
+}
+
+/// Caches the pawn structure evaluation by the pawn only Zobrist key.
+/// Pawn structures change rarely during the search, so most lookups hit.
+/// Must be cleared when the evaluation parameters change.
+pub struct PawnHashTable {
+    entries: Vec<PawnEntry>,
+}
+
+impl PawnHashTable {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![PawnEntry::default(); PAWN_TABLE_ENTRIES],
+        }
+    }
+    pub fn clear(&mut self) {
+        self.entries.fill(PawnEntry::default());
+    }
+    pub fn probe(&mut self, board: &Board, params: &EvalParams) -> PawnEntry {
+        let key = board.pawn_key();
+        let index = key as usize & (PAWN_TABLE_ENTRIES - 1);
+        let entry = self.entries[index];
+        if entry.valid && entry.key == key {
+            return entry;
+        }
+        let entry = evaluate_pawns(board, key, params);
+        self.entries[index] = entry;
+        entry
+    }
+}
+
+pub fn file_mask(file: u8) -> u64 {
+    FILE_A << file
+}
+pub fn adjacent_files_mask(file: u8) -> u64 {
+    let mut mask = 0;
+    if file > 0 {
+        mask |= file_mask(file - 1);
+    }
+    if file < 7 {
+        mask |= file_mask(file + 1);
+    }
+    mask
+}
+/// Every rank strictly in front of `rank` from the point of view of `color`
+pub fn forward_ranks_mask(color: Player, rank: u8) -> u64 {
+    if color == Player::White {
+        if rank >= 7 { 0 } else { u64::MAX << (8 * (rank + 1)) }
+    } else {
+        if rank == 0 { 0 } else { u64::MAX >> (8 * (8 - rank)) }
+    }
+}
+pub fn passed_pawn_mask(color: Player, sq: u8) -> u64 {
+    let file = sq % 8;
+    (file_mask(file) | adjacent_files_mask(file)) & forward_ranks_mask(color, sq / 8)
+}
+pub fn pawn_attacks(color: Player, pawns: u64) -> u64 {
+    if color == Player::White {
+        ((pawns << 7) & !FILE_H) | ((pawns << 9) & !FILE_A)
+    } else {
+        ((pawns >> 9) & !FILE_H) | ((pawns >> 7) & !FILE_A)
+    }
+}
+pub fn relative_rank(color: Player, sq: u8) -> usize {
+    if color == Player::White { (sq / 8) as usize } else { 7 - (sq / 8) as usize }
+}
+
+fn evaluate_pawns(board: &Board, key: u64, params: &EvalParams) -> PawnEntry {
+    let mut entry = PawnEntry {
+        key,
+        valid: true,
+        scores: [Score::ZERO; 2],
+    };
+    for color in [Player::White, Player::Black] {
+        entry.scores[color as usize] = evaluate_side(board, color, params);
+    }
+    entry
+}
+
+fn evaluate_side(board: &Board, color: Player, params: &EvalParams) -> Score {
+    let own_pawns = board.piece_bb(color, PieceType::P).0;
+    let enemy_pawns = board.piece_bb(!color, PieceType::P).0;
+    let enemy_attacks = pawn_attacks(!color, enemy_pawns);
+    let mut score = Score::ZERO;
+
+    let mut remaining = own_pawns;
+    while remaining != 0 {
+        let sq = remaining.trailing_zeros() as u8;
+        remaining &= remaining - 1;
+
+        let file = sq % 8;
+        let rank = relative_rank(color, sq);
+        let sq_bb = 1u64 << sq;
+        let adjacent = adjacent_files_mask(file);
+        let ahead_on_file = file_mask(file) & forward_ranks_mask(color, sq / 8);
+        let ahead_mask = passed_pawn_mask(color, sq);
+
+        let is_passed = ahead_mask & enemy_pawns == 0;
+        let is_isolated = adjacent & own_pawns == 0;
+        let is_doubled = ahead_on_file & own_pawns != 0;
+        let phalanx = adjacent & own_pawns & (0xFFu64 << (8 * (sq / 8))) != 0;
+        let supported = pawn_attacks(!color, sq_bb) & own_pawns != 0;
+
+        if is_passed {
+            score += params.passed_pawn_bonus[rank];
+        } else if ahead_on_file & enemy_pawns == 0 {
+            // Candidate: the file in front is free and there are enough helpers to outnumber the sentries
+            let sentries = (ahead_mask & enemy_pawns).count_ones();
+            let helpers = (adjacent & own_pawns & !forward_ranks_mask(color, sq / 8)).count_ones();
+            if helpers >= sentries {
+                score += params.candidate_pawn_bonus[rank];
+            }
+        }
+
+        if is_isolated {
+            score += params.isolated_pawn_penalty;
+        } else if !phalanx && !supported {
+            // Backward: no pawn on the adjacent files can come to support it,
+            // and advancing would step onto a square guarded by an enemy pawn
+            let supporters_behind = adjacent & own_pawns & !forward_ranks_mask(color, sq / 8);
+            let stop_square = if color == Player::White { sq_bb << 8 } else { sq_bb >> 8 };
+            if supporters_behind == 0 && stop_square & enemy_attacks != 0 {
+                score += params.backward_pawn_penalty;
+            }
+        }
+        if is_doubled {
+            score += params.doubled_pawn_penalty;
+        }
+        if phalanx || supported {
+            score += params.connected_pawn_bonus[rank];
+        }
+    }
+    score
+}
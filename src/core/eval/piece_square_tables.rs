@@ -1,5 +1,6 @@
-use pleco::{BitBoard, Board, PieceType, Player, SQ};
-use crate::mathutils::lerp;
+use pleco::{BitBoard, PieceType, Player, SQ};
+use crate::core::eval::params::EvalParams;
+use crate::core::eval::score::Score;
 
 pub const PAWN_TABLE: [i32; 64] =
 	[0,  0,  0,  0,  0,  0,  0,  0,
@@ -11,6 +12,16 @@ pub const PAWN_TABLE: [i32; 64] =
 	5, 10, 10,-20,-20, 10, 10,  5,
 	0,  0,  0,  0,  0,  0,  0,  0];
 
+pub const PAWN_END_TABLE: [i32; 64] =
+	[0,  0,  0,  0,  0,  0,  0,  0,
+	80, 80, 80, 80, 80, 80, 80, 80,
+	50, 50, 50, 50, 50, 50, 50, 50,
+	30, 30, 30, 30, 30, 30, 30, 30,
+	20, 20, 20, 20, 20, 20, 20, 20,
+	10, 10, 10, 10, 10, 10, 10, 10,
+	10, 10, 10, 10, 10, 10, 10, 10,
+	0,  0,  0,  0,  0,  0,  0,  0];
+
 pub const KNIGHT_TABLE: [i32; 64] =
 	[-50,-40,-30,-30,-30,-30,-40,-50,
 	-40,-20,  0,  0,  0,  0,-20,-40,
@@ -78,47 +89,31 @@ impl PieceSquareTables {
 	/// bitboard: The bitboard of the given piece
 	/// piece: The piece to check
 	/// color: The color of the piece
-	/// endgame_weight: How close is the board to endgame (0, 1)
-	pub fn eval_piece(bitboard: &BitBoard, piece: PieceType, color: Player, endgame_weight: f32) -> f32 {
-		let mut eval = 0.0;
-		let mut endgame_importance = 0.;
+	pub fn eval_piece(bitboard: &BitBoard, piece: PieceType, color: Player, params: &EvalParams) -> Score {
+		let mut eval = Score::ZERO;
 		bitboard.for_each(|square| {
-			match piece {
-				PieceType::P => endgame_importance = 0.5,
-				PieceType::N => endgame_importance = 0.2,
-				PieceType::B => endgame_importance = 0.5,
-				PieceType::R => endgame_importance = 0.3,
-				PieceType::Q => endgame_importance = 0.8,
-				PieceType::K =>  endgame_importance = 1.0,
-				_ => {}
-			}
-			eval += PieceSquareTables::get_value_square(square, piece, color, endgame_weight);
+			eval += PieceSquareTables::get_value_square(square, piece, color, params);
 		});
 		eval
 	}
-	pub fn eval_king(square: SQ, color: Player, endgame_weight: f32) -> f32 {
-		
-		PieceSquareTables::get_value_square(square, PieceType::K, color, endgame_weight)
+	pub fn eval_king(square: SQ, color: Player, params: &EvalParams) -> Score {
+		PieceSquareTables::get_value_square(square, PieceType::K, color, params)
 	}
-	fn get_value_square(square: SQ, piece: PieceType, color: Player, endgame_weight: f32) -> f32{
+	/// Middlegame and endgame values of the piece on the square
+	pub fn get_value_square(square: SQ, piece: PieceType, color: Player, params: &EvalParams) -> Score {
+		// The tables start at a8, so white flips the rank only. `63 - square` would also flip the file.
 		let mut square_index = square.0 as usize;
 		if color == Player::White {
-			square_index = 63 - square_index;
+			square_index ^= 56;
 		}
 		match piece {
-			PieceType::P => PAWN_TABLE[square_index] as f32,
-			PieceType::N => KNIGHT_TABLE[square_index] as f32,
-			PieceType::B => BISHOP_TABLE[square_index] as f32,
-			PieceType::R => ROOK_TABLE[square_index] as f32,
-			PieceType::Q => QUEEN_TABLE[square_index] as f32,
-			PieceType::K => {
-				let king_mid = KING_MID_TABLE[square_index];
-				let king_end = KING_END_TABLE[square_index];
-				lerp(king_mid as f32, king_end as f32, endgame_weight)
-			}
-			_ => {
-				0.0
-			}
+			PieceType::P => params.pawn_table[square_index],
+			PieceType::N => params.knight_table[square_index],
+			PieceType::B => params.bishop_table[square_index],
+			PieceType::R => params.rook_table[square_index],
+			PieceType::Q => params.queen_table[square_index],
+			PieceType::K => params.king_table[square_index],
+			_ => Score::ZERO
 		}
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,102 @@
+use pleco::{Board, PieceType, Player};
+use pleco::helper::prelude::{bishop_moves, knight_moves, queen_moves, rook_moves};
+use crate::core::eval::pawns::{adjacent_files_mask, file_mask, passed_pawn_mask, pawn_attacks, relative_rank};
+use crate::core::eval::params::EvalParams;
+use crate::core::eval::score::Score;
+
+/// Piece terms of one side
+#[derive(Default)]
+pub struct PieceTerms {
+    pub knight_mobility: Score,
+    pub bishop_mobility: Score,
+    pub rook_mobility: Score,
+    pub queen_mobility: Score,
+    pub bishop_pair: Score,
+    pub rook_files: Score,
+    pub rook_seventh: Score,
+    pub knight_outposts: Score,
+    pub trapped_pieces: Score,
+}
+
+pub fn evaluate_pieces(board: &Board, color: Player, params: &EvalParams) -> PieceTerms {
+    let mut terms = PieceTerms::default();
+    let occupied = board.occupied();
+    let own_pawns = board.piece_bb(color, PieceType::P).0;
+    let enemy_pawns = board.piece_bb(!color, PieceType::P).0;
+    let own_pieces = board.get_occupied_player(color).0;
+    // Squares taken by our own pieces or guarded by enemy pawns aren't worth counting
+    let mobility_area = !own_pieces & !pawn_attacks(!color, enemy_pawns);
+    let own_king = board.king_sq(color).0;
+    let enemy_king = board.king_sq(!color).0;
+
+    for sq in board.piece_bb(color, PieceType::N) {
+        let moves = (knight_moves(sq).0 & mobility_area).count_ones() as i32;
+        terms.knight_mobility += params.knight_mobility * (moves - params.knight_average_mobility);
+
+        // Outpost: on the enemy half, supported by a pawn and out of reach of enemy pawns
+        let rank = relative_rank(color, sq.0);
+        let supported = pawn_attacks(!color, sq.to_bb().0) & own_pawns != 0;
+        let attackable = passed_pawn_mask(color, sq.0) & adjacent_files_mask(sq.0 % 8) & enemy_pawns != 0;
+        if (3..=5).contains(&rank) && supported && !attackable {
+            terms.knight_outposts += params.knight_outpost;
+        }
+    }
+
+    for sq in board.piece_bb(color, PieceType::B) {
+        let moves = (bishop_moves(occupied, sq).0 & mobility_area).count_ones() as i32;
+        terms.bishop_mobility += params.bishop_mobility * (moves - params.bishop_average_mobility);
+
+        if is_trapped_bishop(sq.0, color, enemy_pawns) {
+            terms.trapped_pieces += params.trapped_bishop;
+        }
+    }
+    if board.count_piece(color, PieceType::B) >= 2 {
+        terms.bishop_pair += params.bishop_pair;
+    }
+
+    for sq in board.piece_bb(color, PieceType::R) {
+        let moves = (rook_moves(occupied, sq).0 & mobility_area).count_ones() as i32;
+        terms.rook_mobility += params.rook_mobility * (moves - params.rook_average_mobility);
+
+        let file = file_mask(sq.0 % 8);
+        if file & (own_pawns | enemy_pawns) == 0 {
+            terms.rook_files += params.rook_open_file;
+        } else if file & own_pawns == 0 {
+            terms.rook_files += params.rook_semi_open_file;
+        }
+
+        // On the 7th only matters if it attacks pawns there or cuts the king on the 8th
+        let seventh_rank: u64 = if color == Player::White { 0xFF << 48 } else { 0xFF << 8 };
+        if relative_rank(color, sq.0) == 6 && (enemy_pawns & seventh_rank != 0 || relative_rank(color, enemy_king) == 7) {
+            terms.rook_seventh += params.rook_on_seventh;
+        }
+
+        if moves <= 3 && is_trapped_rook(sq.0, own_king, color) {
+            terms.trapped_pieces += params.trapped_rook;
+        }
+    }
+
+    for sq in board.piece_bb(color, PieceType::Q) {
+        let moves = (queen_moves(occupied, sq).0 & mobility_area).count_ones() as i32;
+        terms.queen_mobility += params.queen_mobility * (moves - params.queen_average_mobility);
+    }
+
+    terms
+}
+
+/// Bishop that took the a7/h7 pawn and got cut off by the b6/g6 pawn
+fn is_trapped_bishop(sq: u8, color: Player, enemy_pawns: u64) -> bool {
+    // a7 + b6 and h7 + g6 for white, a2 + b3 and h2 + g3 for black
+    let traps: [(u8, u8); 2] = if color == Player::White { [(48, 41), (55, 46)] } else { [(8, 17), (15, 22)] };
+    traps.iter().any(|&(bishop_sq, pawn_sq)| sq == bishop_sq && enemy_pawns & (1u64 << pawn_sq) != 0)
+}
+
+/// Rook stuck in the corner by its own uncastled king
+fn is_trapped_rook(sq: u8, king: u8, color: Player) -> bool {
+    if relative_rank(color, sq) != 0 || relative_rank(color, king) != 0 {
+        return false;
+    }
+    let (rook_file, king_file) = (sq % 8, king % 8);
+    (king_file >= 5 && rook_file > king_file) || (king_file <= 2 && rook_file < king_file)
+}
+
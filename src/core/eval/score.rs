@@ -0,0 +1,81 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+use serde::{Deserialize, Serialize};
+
+/// Middlegame and endgame values packed in a single integer, the endgame one in the upper 16 bits.
+/// Both halves are added and subtracted at once, and only split when tapering by the game phase.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(from = "(i32, i32)", into = "(i32, i32)")]
+pub struct Score(i32);
+
+/// Phase of a board with all the pieces still on it
+pub const MAX_PHASE: i32 = 24;
+
+impl Score {
+    pub const ZERO: Score = Score(0);
+
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Score((eg << 16).wrapping_add(mg))
+    }
+    pub const fn mg(self) -> i32 {
+        self.0 as i16 as i32
+    }
+    pub const fn eg(self) -> i32 {
+        (self.0.wrapping_add(0x8000) >> 16) as i16 as i32
+    }
+    /// Interpolates between the middlegame and endgame values.
+    /// `phase` goes from `MAX_PHASE` in the opening down to 0 with only pawns and kings left.
+    pub fn taper(self, phase: i32) -> i32 {
+        (self.mg() * phase + self.eg() * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+    /// Same as `taper`, with the endgame value scaled by `scale / scale_normal` first
+    pub fn taper_scaled(self, phase: i32, scale: i32, scale_normal: i32) -> i32 {
+        let eg = self.eg() * scale / scale_normal;
+        (self.mg() * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl From<(i32, i32)> for Score {
+    fn from((mg, eg): (i32, i32)) -> Score {
+        Score::new(mg, eg)
+    }
+}
+impl From<Score> for (i32, i32) {
+    fn from(score: Score) -> (i32, i32) {
+        (score.mg(), score.eg())
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+    fn add(self, rhs: Score) -> Score {
+        Score(self.0.wrapping_add(rhs.0))
+    }
+}
+impl AddAssign for Score {
+    fn add_assign(&mut self, rhs: Score) {
+        *self = *self + rhs;
+    }
+}
+impl Sub for Score {
+    type Output = Score;
+    fn sub(self, rhs: Score) -> Score {
+        Score(self.0.wrapping_sub(rhs.0))
+    }
+}
+impl SubAssign for Score {
+    fn sub_assign(&mut self, rhs: Score) {
+        *self = *self - rhs;
+    }
+}
+impl Neg for Score {
+    type Output = Score;
+    fn neg(self) -> Score {
+        Score(self.0.wrapping_neg())
+    }
+}
+impl Mul<i32> for Score {
+    type Output = Score;
+    fn mul(self, rhs: i32) -> Score {
+        Score(self.0.wrapping_mul(rhs))
+    }
+}
@@ -0,0 +1,123 @@
+use pleco::{Board, Player};
+use crate::core::eval::Evaluator;
+use crate::core::eval::evaluation::ClassicEvaluator;
+
+/// Quiet positions covering the opening, middlegame and endgame, with neither king in check
+const CORPUS: [&str; 12] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 0 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "2r3k1/pp3ppp/8/3p4/3P4/8/PP3PPP/2R3K1 b - - 0 20",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "8/5pk1/6p1/3Q4/8/6P1/5PK1/2q5 b - - 0 40",
+    "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+    "8/8/4k3/3n4/8/2B5/4K3/8 w - - 0 1",
+    "8/8/8/3k4/8/8/8/KBN5 w - - 0 1",
+];
+
+fn evaluate(fen: &str) -> i32 {
+    let board = Board::from_fen(fen).unwrap();
+    ClassicEvaluator::new().evaluate_board(&board)
+}
+
+/// Swaps the colors: ranks in reverse order, pieces and castling rights of the other side,
+/// and the other side to move
+fn color_flip(fen: &str) -> String {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let swap_case = |c: char| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() };
+
+    let placement = fields[0].split('/').rev().map(|rank| rank.chars().map(swap_case).collect::<String>()).collect::<Vec<_>>().join("/");
+    let turn = if fields[1] == "w" { "b" } else { "w" };
+    let castling = if fields[2] == "-" {
+        "-".to_string()
+    } else {
+        let swapped: String = fields[2].chars().map(swap_case).collect();
+        let (white, black): (String, String) = swapped.chars().partition(|c| c.is_ascii_uppercase());
+        white + &black
+    };
+    let en_passant = match fields[3] {
+        "-" => "-".to_string(),
+        sq => format!("{}{}", &sq[0..1], if &sq[1..2] == "3" { "6" } else { "3" }),
+    };
+    format!("{} {} {} {} {} {}", placement, turn, castling, en_passant, fields[4], fields[5])
+}
+
+/// Mirrors the board left to right. Only valid without castling rights.
+fn horizontal_mirror(fen: &str) -> String {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    assert_eq!(fields[2], "-", "castling rights don't survive a horizontal mirror");
+    let placement = fields[0].split('/').map(|rank| rank.chars().rev().collect::<String>()).collect::<Vec<_>>().join("/");
+    let en_passant = match fields[3] {
+        "-" => "-".to_string(),
+        sq => format!("{}{}", (b'h' - (sq.as_bytes()[0] - b'a')) as char, &sq[1..2]),
+    };
+    format!("{} {} {} {} {} {}", placement, fields[1], fields[2], en_passant, fields[4], fields[5])
+}
+
+/// Same position with the other side to move
+fn pass_turn(fen: &str) -> String {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let turn = if fields[1] == "w" { "b" } else { "w" };
+    format!("{} {} {} - {} {}", fields[0], turn, fields[2], fields[4], fields[5])
+}
+
+#[test]
+fn color_flip_is_an_involution() {
+    for fen in CORPUS {
+        assert_eq!(color_flip(&color_flip(fen)), fen);
+    }
+}
+
+#[test]
+fn color_flip_keeps_the_eval_of_the_side_to_move() {
+    for fen in CORPUS {
+        let flipped = color_flip(fen);
+        assert_eq!(evaluate(fen), evaluate(&flipped), "{} and {} should evaluate the same", fen, flipped);
+    }
+}
+
+#[test]
+fn color_flip_swaps_every_term() {
+    for fen in CORPUS {
+        let flipped = color_flip(fen);
+        let trace = ClassicEvaluator::new().trace(&Board::from_fen(fen).unwrap());
+        let flipped_trace = ClassicEvaluator::new().trace(&Board::from_fen(&flipped).unwrap());
+        assert_eq!(trace.phase, flipped_trace.phase);
+        for term in &trace.terms {
+            assert_eq!(Some(term.white), flipped_trace.get(term.name, Player::Black), "{} of white in {}", term.name, fen);
+            assert_eq!(Some(term.black), flipped_trace.get(term.name, Player::White), "{} of black in {}", term.name, fen);
+        }
+    }
+}
+
+#[test]
+fn horizontal_mirror_keeps_every_term_but_the_piece_square_tables() {
+    // The tables favour one wing (castled king squares, queen placement), everything else is expected to be symmetric
+    for fen in CORPUS.iter().filter(|fen| fen.split_whitespace().nth(2) == Some("-")) {
+        let mirrored = horizontal_mirror(fen);
+        let trace = ClassicEvaluator::new().trace(&Board::from_fen(fen).unwrap());
+        let mirrored_trace = ClassicEvaluator::new().trace(&Board::from_fen(&mirrored).unwrap());
+        assert_eq!(trace.endgame, mirrored_trace.endgame, "known ending of {}", fen);
+        for term in trace.terms.iter().filter(|t| !t.name.starts_with("PST")) {
+            assert_eq!(Some(term.white), mirrored_trace.get(term.name, Player::White), "{} of white in {}", term.name, fen);
+            assert_eq!(Some(term.black), mirrored_trace.get(term.name, Player::Black), "{} of black in {}", term.name, fen);
+        }
+    }
+}
+
+#[test]
+fn passing_the_turn_negates_the_eval() {
+    // There is no tempo bonus, so the side to move only changes the sign
+    for fen in CORPUS {
+        let passed = pass_turn(fen);
+        assert_eq!(evaluate(fen), -evaluate(&passed), "{} and {} should be opposite", fen, passed);
+    }
+}
+
+#[test]
+fn start_position_is_balanced() {
+    assert_eq!(evaluate(CORPUS[0]), 0);
+}
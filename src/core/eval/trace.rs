@@ -0,0 +1,99 @@
+use std::fmt;
+use pleco::Player;
+use crate::core::eval::endgames::SCALE_NORMAL;
+use crate::core::eval::score::{Score, MAX_PHASE};
+
+/// One line of the evaluation breakdown
+pub struct TraceTerm {
+    pub name: &'static str,
+    pub white: Score,
+    pub black: Score,
+}
+
+/// Per-term breakdown of a `ClassicEvaluator` evaluation, from white's point of view
+pub struct EvalTrace {
+    pub phase: i32,
+    /// Scale factor applied to the endgame score, out of `SCALE_NORMAL`
+    pub scale: i32,
+    /// Set when a known ending was evaluated by a dedicated function instead of the terms
+    pub endgame: Option<i32>,
+    pub terms: Vec<TraceTerm>,
+}
+
+impl EvalTrace {
+    pub fn new(phase: i32) -> Self {
+        Self {
+            phase,
+            scale: SCALE_NORMAL,
+            endgame: None,
+            terms: vec![],
+        }
+    }
+    pub fn add(&mut self, name: &'static str, white: Score, black: Score) {
+        self.terms.push(TraceTerm { name, white, black });
+    }
+    pub fn get(&self, name: &str, color: Player) -> Option<Score> {
+        self.terms.iter()
+            .find(|t| t.name == name)
+            .map(|t| if color == Player::White { t.white } else { t.black })
+    }
+    /// Sum of every term, white minus black
+    pub fn total(&self) -> Score {
+        self.terms.iter().fold(Score::ZERO, |total, t| total + t.white - t.black)
+    }
+    /// The evaluation the terms add up to, from white's point of view
+    pub fn final_eval(&self) -> i32 {
+        self.endgame.unwrap_or_else(|| self.total().taper_scaled(self.phase, self.scale, SCALE_NORMAL))
+    }
+}
+
+fn pawns(cp: i32) -> String {
+    format!("{:.2}", cp as f64 / 100.0)
+}
+fn score_columns(score: Score) -> String {
+    format!("{:>6} {:>6}", pawns(score.mg()), pawns(score.eg()))
+}
+
+/// Prints the breakdown as a table in pawns, like Stockfish's `eval` command
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = " ------------------+---------------+---------------+---------------";
+        writeln!(f, "        Term       |     White     |     Black     |     Total")?;
+        writeln!(f, "                   |   MG     EG   |   MG     EG   |   MG     EG")?;
+        writeln!(f, "{}", separator)?;
+        for term in &self.terms {
+            writeln!(f, " {:>17} | {} | {} | {}", term.name, score_columns(term.white), score_columns(term.black),
+                     score_columns(term.white - term.black))?;
+        }
+        writeln!(f, "{}", separator)?;
+        writeln!(f, " {:>17} | {:>13} | {:>13} | {}", "Total", "", "", score_columns(self.total()))?;
+        writeln!(f)?;
+        writeln!(f, "Phase: {} / {}", self.phase, MAX_PHASE)?;
+        writeln!(f, "Endgame scale: {} / {}", self.scale, SCALE_NORMAL)?;
+        if let Some(eval) = self.endgame {
+            writeln!(f, "Known ending, evaluated as {} instead of the terms", pawns(eval))?;
+        }
+        write!(f, "Final evaluation: {} (white side)", pawns(self.final_eval()))
+    }
+}
+
+/// Sums the terms of the evaluation, recording them in the trace when one is being built
+pub struct TermAccumulator<'a> {
+    pub total: Score,
+    trace: Option<&'a mut EvalTrace>,
+}
+
+impl<'a> TermAccumulator<'a> {
+    pub fn new(trace: Option<&'a mut EvalTrace>) -> Self {
+        Self {
+            total: Score::ZERO,
+            trace,
+        }
+    }
+    pub fn add(&mut self, name: &'static str, white: Score, black: Score) {
+        self.total += white - black;
+        if let Some(trace) = self.trace.as_mut() {
+            trace.add(name, white, black);
+        }
+    }
+}
@@ -3,7 +3,6 @@ use pleco::board::movegen::{Legal, MoveGen};
 use pleco::core::GenTypes;
 use pleco::core::mono_traits::{AllGenType, CapturesGenType};
 use rand::random;
-use crate::core::eval::evaluation::ClassicEvaluator;
 use crate::core::search::search::{Searcher};
 use crate::core::search::transposition_table::TranspositionTable;
 
@@ -28,9 +27,9 @@ pub fn order_moves(board: &Board, searcher: &Searcher, depth: u8, quiesence_sear
         let killer = if is_killer { 99999 } else { 0 };
 
         // MVV-LVA
-        let victim_value = ClassicEvaluator::capture_value(board, m);
+        let victim_value = searcher.evaluator.capture_value(board, m);
         let moved_piece = board.piece_at_sq(m.get_src());
-        let attacker_value = ClassicEvaluator::piece_value(moved_piece.type_of());
+        let attacker_value = searcher.evaluator.piece_value(moved_piece.type_of());
         let mut mvv_lva_score = victim_value * 10 - attacker_value;
         if victim_value == 0 {
             mvv_lva_score = 0;
@@ -8,6 +8,7 @@ pub mod search;
 pub mod eval;
 pub mod heuristics;
 pub mod openings;
+pub mod endgame_tables;
 
 
 /// This is really slow
@@ -20,24 +21,36 @@ pub fn move_to_san(board: &Board, m: &BitMove) -> String {
 pub struct Limiter {
     time: Option<std::time::Duration>,
     depth: Option<u8>,
+    nodes: Option<u64>,
 }
 impl Limiter {
     pub fn time(duration: std::time::Duration) -> Self {
         Self {
             time: Some(duration),
             depth: None,
+            nodes: None,
         }
     }
     pub fn depth(depth: u8) -> Self {
         Self {
             time: None,
             depth: Some(depth),
+            nodes: None,
         }
     }
     pub fn both(depth: u8, time: Duration) -> Self {
         Self {
             time: Some(time),
             depth: Some(depth),
+            nodes: None,
+        }
+    }
+    /// Stops after a fixed number of nodes, so the result doesn't depend on the machine speed
+    pub fn nodes(nodes: u64) -> Self {
+        Self {
+            time: None,
+            depth: None,
+            nodes: Some(nodes),
         }
     }
     pub fn is_time(&self) -> bool {
@@ -52,4 +65,7 @@ impl Limiter {
     pub fn get_depth(&self) -> Option<u8> {
         self.depth.clone()
     }
+    pub fn get_nodes(&self) -> Option<u64> {
+        self.nodes
+    }
 }
\ No newline at end of file
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use pgn_reader::{BufferedReader, RawHeader, SanPlus, Skip, Visitor};
+use shakmaty::{CastlingMode, Chess, Color, EnPassantMode, Move, Outcome, Position, Role};
+use shakmaty::uci::UciMove;
+use shakmaty::zobrist::{Zobrist64, ZobristHash};
+use crate::core::openings::polyglot::PolyglotEntry;
+
+pub struct BookBuildConfig {
+    pub pgn: String,
+    pub output: String,
+    /// Moves after this many plies aren't recorded
+    pub max_ply: usize,
+    /// Moves played in fewer games are left out
+    pub min_count: u32,
+    /// Weight of a game the moving side won, drew and lost
+    pub win_weight: u32,
+    pub draw_weight: u32,
+    pub loss_weight: u32,
+    /// Games where either player is rated below this (or unrated) are skipped
+    pub min_elo: Option<u32>,
+}
+
+impl BookBuildConfig {
+    /// `book build <pgn> [output] [--max-ply n] [--min-count n] [--win n] [--draw n] [--loss n] [--min-elo n]`
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        let mut positional = vec![];
+        let mut config = Self {
+            pgn: String::new(),
+            output: "book.bin".to_string(),
+            max_ply: 20,
+            min_count: 1,
+            win_weight: 2,
+            draw_weight: 1,
+            loss_weight: 0,
+            min_elo: None,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--max-ply" => config.max_ply = args.next()?.parse().ok()?,
+                "--min-count" => config.min_count = args.next()?.parse().ok()?,
+                "--win" => config.win_weight = args.next()?.parse().ok()?,
+                "--draw" => config.draw_weight = args.next()?.parse().ok()?,
+                "--loss" => config.loss_weight = args.next()?.parse().ok()?,
+                "--min-elo" => config.min_elo = Some(args.next()?.parse().ok()?),
+                _ => positional.push(arg.clone()),
+            }
+        }
+        config.pgn = positional.first()?.clone();
+        if let Some(output) = positional.get(1) {
+            config.output = output.clone();
+        }
+        Some(config)
+    }
+}
+
+/// Results of a move from the point of view of the side playing it
+#[derive(Default, Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MoveStats {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// Games without a result (`*`), which still show the move is played
+    pub unfinished: u32,
+}
+
+impl MoveStats {
+    /// Every game the move was played in
+    pub fn count(&self) -> u32 {
+        self.finished() + self.unfinished
+    }
+    /// Games with a result
+    pub fn finished(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+    fn record(&mut self, outcome: Option<Outcome>, color: Color) {
+        match outcome {
+            Some(Outcome::Decisive { winner }) if winner == color => self.wins += 1,
+            Some(Outcome::Decisive { .. }) => self.losses += 1,
+            Some(Outcome::Draw) => self.draws += 1,
+            None => self.unfinished += 1,
+        }
+    }
+}
+
+/// Polyglot encoding of a move: to file/rank, from file/rank, then the promotion piece.
+/// Castling is the king taking its own rook, which is how shakmaty writes it in Chess960 mode.
+pub fn encode_polyglot_move(m: &Move) -> Option<u16> {
+    let UciMove::Normal { from, to, promotion } = m.to_uci(CastlingMode::Chess960) else {
+        return None;
+    };
+    let (from, to) = (u32::from(from) as u16, u32::from(to) as u16);
+    let promotion = match promotion {
+        Some(Role::Knight) => 1,
+        Some(Role::Bishop) => 2,
+        Some(Role::Rook) => 3,
+        Some(Role::Queen) => 4,
+        _ => 0,
+    };
+    Some((to % 8) | (to / 8) << 3 | (from % 8) << 6 | (from / 8) << 9 | promotion << 12)
+}
+
+/// Records the first moves of each game with the key of the position they were played from
+struct BookVisitor {
+    max_ply: usize,
+    /// Games where either player is rated below this (or unrated) are skipped
+    min_elo: Option<u32>,
+    position: Chess,
+    moves: Vec<(u64, u16, Color)>,
+    white_elo: Option<u32>,
+    black_elo: Option<u32>,
+    outcome: Option<Outcome>,
+    valid: bool,
+}
+
+impl BookVisitor {
+    fn new(max_ply: usize, min_elo: Option<u32>) -> Self {
+        Self {
+            max_ply,
+            min_elo,
+            position: Chess::new(),
+            moves: vec![],
+            white_elo: None,
+            black_elo: None,
+            outcome: None,
+            valid: true,
+        }
+    }
+}
+
+impl Visitor for BookVisitor {
+    type Result = Option<(Vec<(u64, u16, Color)>, Option<Outcome>)>;
+
+    fn begin_game(&mut self) {
+        self.position = Chess::new();
+        self.moves.clear();
+        self.white_elo = None;
+        self.black_elo = None;
+        self.outcome = None;
+        self.valid = true;
+    }
+    fn header(&mut self, key: &[u8], value: RawHeader<'_>) {
+        let elo = || value.decode_utf8().ok().and_then(|v| v.trim().parse().ok());
+        match key {
+            b"WhiteElo" => self.white_elo = elo(),
+            b"BlackElo" => self.black_elo = elo(),
+            // Games from a set up position can't be followed from the start
+            b"FEN" => self.valid = false,
+            _ => {}
+        }
+    }
+    fn end_headers(&mut self) -> Skip {
+        if let Some(min_elo) = self.min_elo {
+            let rated = |elo: Option<u32>| elo.is_some_and(|elo| elo >= min_elo);
+            if !rated(self.white_elo) || !rated(self.black_elo) {
+                self.valid = false;
+            }
+        }
+        Skip(!self.valid)
+    }
+    fn san(&mut self, san_plus: SanPlus) {
+        if !self.valid || self.moves.len() >= self.max_ply {
+            return;
+        }
+        let Ok(m) = san_plus.san.to_move(&self.position) else {
+            self.valid = false;
+            return;
+        };
+        // Pseudo legal en passant is the Polyglot rule, so the keys match books from other tools
+        let key = self.position.zobrist_hash::<Zobrist64>(EnPassantMode::PseudoLegal).0;
+        if let Some(encoded) = encode_polyglot_move(&m) {
+            self.moves.push((key, encoded, self.position.turn()));
+        }
+        self.position.play_unchecked(&m);
+    }
+    fn begin_variation(&mut self) -> Skip {
+        Skip(true)
+    }
+    fn outcome(&mut self, outcome: Option<Outcome>) {
+        self.outcome = outcome;
+    }
+    fn end_game(&mut self) -> Self::Result {
+        if !self.valid {
+            return None;
+        }
+        Some((std::mem::take(&mut self.moves), self.outcome))
+    }
+}
+
+/// Reads the PGN database and gathers the results of each (position key, move) pair played in the
+/// first `max_ply` plies. Returns them with the number of games read.
+pub fn read_move_stats(path: &str, max_ply: usize, min_elo: Option<u32>) -> io::Result<(HashMap<(u64, u16), MoveStats>, usize)> {
+    let mut reader = BufferedReader::new(File::open(path)?);
+    let mut visitor = BookVisitor::new(max_ply, min_elo);
+    let mut stats: HashMap<(u64, u16), MoveStats> = HashMap::new();
+    let mut games = 0;
+
+    while let Some(game) = reader.read_game(&mut visitor)? {
+        let Some((moves, outcome)) = game else {
+            continue;
+        };
+        games += 1;
+        for (key, mv, color) in moves {
+            stats.entry((key, mv)).or_default().record(outcome, color);
+        }
+    }
+    Ok((stats, games))
+}
+
+/// Builds a Polyglot book from a PGN database. Each move gets the weighted sum of the results
+/// the moving side had with it, scaled down to fit the 16 bits weight if needed.
+/// Unfinished games count towards `min_count` but add no weight.
+/// Returns the number of entries written.
+pub fn build_book(config: &BookBuildConfig) -> io::Result<usize> {
+    let (stats, games) = read_move_stats(&config.pgn, config.max_ply, config.min_elo)?;
+
+    let weight = |s: &MoveStats| s.wins as u64 * config.win_weight as u64
+        + s.draws as u64 * config.draw_weight as u64
+        + s.losses as u64 * config.loss_weight as u64;
+    let kept: Vec<((u64, u16), u64)> = stats.iter()
+        .filter(|(_, s)| s.count() >= config.min_count)
+        .map(|(&k, s)| (k, weight(s)))
+        .filter(|&(_, w)| w > 0)
+        .collect();
+    let max_weight = kept.iter().map(|&(_, w)| w).max().unwrap_or(0);
+    let scale = max_weight.div_ceil(u16::MAX as u64).max(1);
+
+    let mut entries: Vec<PolyglotEntry> = kept.into_iter()
+        .map(|((key, mv), w)| PolyglotEntry { key, mv, weight: (w / scale).max(1) as u16, learn: 0 })
+        .collect();
+    entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)).then(a.mv.cmp(&b.mv)));
+
+    let mut data = Vec::with_capacity(entries.len() * 16);
+    entries.iter().for_each(|e| e.write(&mut data));
+    fs::write(&config.output, data)?;
+    println!("Read {} games, wrote {} entries to {}", games, entries.len(), config.output);
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use shakmaty::{CastlingMode, Chess, Color, Move, Outcome};
+    use shakmaty::fen::Fen;
+    use shakmaty::uci::UciMove;
+    use super::{encode_polyglot_move, BookBuildConfig, MoveStats};
+
+    fn play(fen: &str, uci: &str) -> Move {
+        let position: Chess = Fen::from_ascii(fen.as_bytes()).unwrap().into_position(CastlingMode::Standard).unwrap();
+        UciMove::from_ascii(uci.as_bytes()).unwrap().to_move(&position).unwrap()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn encodes_a_normal_move() {
+        let m = play("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e2e4");
+        assert_eq!(encode_polyglot_move(&m), Some(0x031c));
+    }
+
+    #[test]
+    fn encodes_castling_as_the_king_taking_its_rook() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        // e1h1 and e1a1
+        assert_eq!(encode_polyglot_move(&play(fen, "e1g1")), Some(7 | 4 << 6));
+        assert_eq!(encode_polyglot_move(&play(fen, "e1c1")), Some(4 << 6));
+        // e8a8
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1";
+        assert_eq!(encode_polyglot_move(&play(fen, "e8c8")), Some(7 << 3 | 4 << 6 | 7 << 9));
+    }
+
+    #[test]
+    fn encodes_promotions() {
+        let fen = "8/4P3/8/8/8/k7/8/K7 w - - 0 1";
+        let e7e8 = 4 | 7 << 3 | 4 << 6 | 6 << 9;
+        assert_eq!(encode_polyglot_move(&play(fen, "e7e8n")), Some(e7e8 | 1 << 12));
+        assert_eq!(encode_polyglot_move(&play(fen, "e7e8b")), Some(e7e8 | 2 << 12));
+        assert_eq!(encode_polyglot_move(&play(fen, "e7e8r")), Some(e7e8 | 3 << 12));
+        assert_eq!(encode_polyglot_move(&play(fen, "e7e8q")), Some(e7e8 | 4 << 12));
+    }
+
+    #[test]
+    fn config_defaults() {
+        let config = BookBuildConfig::from_args(&args(&["games.pgn"])).unwrap();
+        assert_eq!(config.pgn, "games.pgn");
+        assert_eq!(config.output, "book.bin");
+        assert_eq!(config.max_ply, 20);
+        assert_eq!(config.min_count, 1);
+        assert_eq!((config.win_weight, config.draw_weight, config.loss_weight), (2, 1, 0));
+        assert_eq!(config.min_elo, None);
+    }
+
+    #[test]
+    fn config_options() {
+        let config = BookBuildConfig::from_args(&args(&[
+            "--max-ply", "12", "games.pgn", "--min-count", "3", "out.bin",
+            "--win", "4", "--draw", "2", "--loss", "1", "--min-elo", "2200",
+        ])).unwrap();
+        assert_eq!(config.pgn, "games.pgn");
+        assert_eq!(config.output, "out.bin");
+        assert_eq!(config.max_ply, 12);
+        assert_eq!(config.min_count, 3);
+        assert_eq!((config.win_weight, config.draw_weight, config.loss_weight), (4, 2, 1));
+        assert_eq!(config.min_elo, Some(2200));
+    }
+
+    #[test]
+    fn config_rejects_bad_arguments() {
+        assert!(BookBuildConfig::from_args(&args(&[])).is_none());
+        assert!(BookBuildConfig::from_args(&args(&["games.pgn", "--max-ply"])).is_none());
+        assert!(BookBuildConfig::from_args(&args(&["games.pgn", "--min-elo", "high"])).is_none());
+    }
+
+    #[test]
+    fn stats_are_from_the_side_playing_the_move() {
+        let mut stats = MoveStats::default();
+        stats.record(Some(Outcome::Decisive { winner: Color::White }), Color::White);
+        stats.record(Some(Outcome::Decisive { winner: Color::White }), Color::Black);
+        stats.record(Some(Outcome::Draw), Color::Black);
+        stats.record(None, Color::White);
+        assert_eq!(stats, MoveStats { wins: 1, draws: 1, losses: 1, unfinished: 1 });
+        assert_eq!(stats.count(), 4);
+        assert_eq!(stats.finished(), 3);
+    }
+}
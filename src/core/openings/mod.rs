@@ -1,125 +1,102 @@
-use std::fs;
-use std::io::read_to_string;
-use std::time::Instant;
-use pgn_reader::{BufferedReader, SanPlus, Visitor};
-use rand::distr::Distribution;
+use std::io;
 use pleco::BitMove;
 use rand::prelude::ThreadRng;
 use rand::thread_rng;
-use shakmaty::{Board, CastlingMode, Chess, Position};
+use crate::core::openings::book_builder::{read_move_stats, MoveStats};
+use crate::core::openings::polyglot::{polyglot_key, polyglot_move_to_bitmove, PolyglotBook};
+use crate::core::openings::strategy::{BookCandidate, BookSelector};
 
-struct MoveGetter {
-    pub moves: Vec<String>,
-    pub current_position: shakmaty::Chess,
-}
-impl MoveGetter {
-    pub fn new() -> Self {
-        Self{
-            moves: vec![],
-            current_position: Chess::new(),
-        }
-    }
-}
-impl Visitor for MoveGetter {
-    type Result = Vec<String>;
-    fn san(&mut self, _san_plus: SanPlus) {
-        if self.moves.len() > 10 {
-            return;
-        }
-        let m = _san_plus.san.to_move(&self.current_position).unwrap();
-        let uci_move = m.to_uci(CastlingMode::Standard);
-        self.current_position.play_unchecked(&m);
-        self.moves.push(uci_move.to_string());
-    }
+pub mod polyglot;
+pub mod book_builder;
+pub mod strategy;
 
-    fn end_game(&mut self) -> Self::Result {
-        self.moves.clone()
-    }
-}
-#[derive(Clone)]
-struct CurrentPosition {
-    pub last_move: String,
-    pub child_positions: Vec<CurrentPosition>,
-    pub count: usize,
-    pub depth: usize,
-}
-impl CurrentPosition {
-    pub fn new(games: Vec<Vec<String>>, depth: usize) -> Self {
-        let mut root = CurrentPosition {
-            last_move: "startpos".to_string(),
-            child_positions: Vec::new(),
-            count: games.len(),
-            depth,
-        };
+/// Moves of the first plies of each game are counted in the book
+const BOOK_PLIES: usize = 11;
 
-        for game in games {
-            root.add_game(&game, 0);
-        }
+/// A move of the book with the results of the games it was played in.
+/// The move uses the 16 bits Polyglot encoding, as small as a `BitMove`.
+#[derive(Copy, Clone)]
+struct BookEntry {
+    key: u64,
+    mv: u16,
+    stats: MoveStats,
+}
 
-        root
+impl BookCandidate for BookEntry {
+    fn mv(&self) -> u16 {
+        self.mv
     }
-    fn add_game(&mut self, game: &[String], move_index: usize) {
-        if move_index >= game.len() {
-            return;
-        }
-
-        let current_move = &game[move_index];
-
-        if let Some(child) = self.child_positions.iter_mut().find(|c| c.last_move == *current_move) {
-            child.count += 1;
-            child.add_game(game, move_index + 1);
-        } else {
-            let mut new_child = CurrentPosition {
-                last_move: current_move.clone(),
-                child_positions: Vec::new(),
-                count: 1,
-                depth: self.depth + 1,
-            };
-            new_child.add_game(game, move_index + 1);
-            self.child_positions.push(new_child);
-        }
+    fn games(&self) -> u32 {
+        self.stats.count()
+    }
+    fn finished_games(&self) -> u32 {
+        self.stats.finished()
+    }
+    fn points(&self) -> f64 {
+        self.stats.wins as f64 + self.stats.draws as f64 / 2.0
     }
 }
+
+/// Book built from a PGN database, indexed by the Polyglot key of each position so it finds
+/// transpositions and works from any starting position.
+/// All the moves live in a single array sorted by key, so a lookup is a binary search.
 pub struct OpeningBook {
     enabled: bool,
-    start_position: CurrentPosition,
-    current_position: CurrentPosition,
+    entries: Vec<BookEntry>,
     rng: ThreadRng,
+    /// Polyglot book, asked before the PGN positions
+    polyglot: Option<PolyglotBook>,
+    /// How a move is picked among the book moves, for both books
+    pub selector: BookSelector,
 }
 
 impl OpeningBook {
+    /// Streams the PGN file, only keeping the results per (position, move) pair.
+    /// Games without a result still count as played, see `MoveStats::unfinished`.
     pub fn load(path: &str) -> Self {
-        let games = Self::get_all_games(path.to_string());
-        let current_position = CurrentPosition::new(games, 0);
-        Self { start_position: current_position.clone(), current_position, rng: thread_rng(), enabled: true }
+        let (stats, _) = read_move_stats(path, BOOK_PLIES, None).unwrap();
+        let mut entries: Vec<BookEntry> = stats.into_iter()
+            .map(|((key, mv), stats)| BookEntry { key, mv, stats })
+            .collect();
+        entries.sort_unstable_by_key(|e| (e.key, e.mv));
+        entries.shrink_to_fit();
+        Self { entries, rng: thread_rng(), enabled: true, polyglot: None, selector: BookSelector::new() }
+    }
+    /// Returns the number of entries in the book
+    pub fn load_polyglot(&mut self, path: &str) -> io::Result<usize> {
+        let book = PolyglotBook::load(path)?;
+        let len = book.len();
+        self.polyglot = Some(book);
+        Ok(len)
+    }
+    pub fn clear_polyglot(&mut self) {
+        self.polyglot = None;
     }
     pub fn is_enabled(&self) -> bool {
         self.enabled
     }
-    fn get_all_games(path: String) -> Vec<Vec<String>> {
-        let pgn_string = fs::read_to_string(path).unwrap();
-        let mut reader = BufferedReader::new_cursor(&pgn_string[..]);
-
-        let mut move_getter = MoveGetter::new();
-        let mut games = vec![];
-        while let Some(moves) = reader.read_game(&mut move_getter).ok().flatten() {
-            games.push(moves);
-            move_getter = MoveGetter::new();
-        }
-        games
+    fn probe(entries: &[BookEntry], key: u64) -> &[BookEntry] {
+        let start = entries.partition_point(|e| e.key < key);
+        let end = start + entries[start..].partition_point(|e| e.key == key);
+        &entries[start..end]
     }
-    pub fn get_next_move(&mut self) -> Option<String> {
-        if !self.enabled { 
-            return None
-        }
-        let possible_moves = &self.current_position.child_positions;
-        if possible_moves.is_empty() {
+    /// Book move for `board`, from the Polyglot book if one is loaded and knows the position,
+    /// else from the PGN games
+    pub fn get_move(&mut self, board: &pleco::Board) -> Option<BitMove> {
+        if !self.enabled {
             return None;
         }
-        let weighted_index = rand::distr::WeightedIndex::new(possible_moves.iter().map(|p| p.count * p.count)).unwrap();
-        let mve = possible_moves[weighted_index.sample(&mut self.rng)].clone();
-        let r = Some(mve.last_move.clone());
-        r
+        let key = polyglot_key(board);
+        if let Some(book) = &self.polyglot {
+            let entries = book.probe(key);
+            let index = self.selector.choose(key, entries, &mut self.rng);
+            if let Some(m) = index.and_then(|i| polyglot_move_to_bitmove(board, entries[i].mv)) {
+                return Some(m);
+            }
+        }
+        let entries = Self::probe(&self.entries, key);
+        let index = self.selector.choose(key, entries, &mut self.rng)?;
+        polyglot_move_to_bitmove(board, entries[index].mv)
     }
     pub fn set_disabled(&mut self) {
         self.enabled = false;
@@ -127,25 +104,4 @@ impl OpeningBook {
     pub fn set_enabled(&mut self) {
         self.enabled = true;
     }
-    pub fn reset(&mut self) {
-        self.current_position = self.start_position.clone();
-    }
-    pub fn apply_move(&mut self, m: String) -> bool {
-        let m: Vec<&CurrentPosition> = self.current_position.child_positions.iter().filter(|p| p.last_move == m).collect();
-        if let Some(&pos) = m.first() { 
-            self.current_position = pos.clone();
-            true
-        } else { false }    
-    }
 }
-
-fn print_tree(node: &CurrentPosition, indent: usize) {
-    println!("{}{} (Depth: {}, Games: {})",
-             "  ".repeat(indent),
-             node.last_move,
-             node.depth,
-             node.count);
-    for child in &node.child_positions {
-        print_tree(child, indent + 1);
-    }
-}
\ No newline at end of file
@@ -0,0 +1,216 @@
+use std::fs;
+use std::io;
+use pleco::{BitMove, Board, PieceType, Player};
+use pleco::core::CastleType;
+use shakmaty::{CastlingSide, Color, File, Role, Square};
+use shakmaty::zobrist::{Zobrist64, ZobristValue};
+use crate::core::eval::incremental::piece_boards;
+use crate::core::eval::pawns::pawn_attacks;
+use crate::core::openings::strategy::BookCandidate;
+
+const ENTRY_SIZE: usize = 16;
+/// Same order as `piece_boards`
+const ROLES: [Role; 6] = [Role::Pawn, Role::Knight, Role::Bishop, Role::Rook, Role::Queen, Role::King];
+
+/// One move of a Polyglot book, as stored on disk (big endian, sorted by key)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PolyglotEntry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl PolyglotEntry {
+    fn read(bytes: &[u8]) -> Self {
+        Self {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.key.to_be_bytes());
+        out.extend_from_slice(&self.mv.to_be_bytes());
+        out.extend_from_slice(&self.weight.to_be_bytes());
+        out.extend_from_slice(&self.learn.to_be_bytes());
+    }
+}
+
+/// Polyglot hash of the position, the key the book entries are sorted by.
+/// Computed straight from the bitboards with shakmaty's Polyglot compatible Zobrist values.
+pub fn polyglot_key(board: &Board) -> u64 {
+    let mut key = Zobrist64(0);
+    for (i, mut bb) in piece_boards(board).into_iter().enumerate() {
+        let piece = shakmaty::Piece {
+            color: if i < 6 { Color::White } else { Color::Black },
+            role: ROLES[i % 6],
+        };
+        while bb != 0 {
+            let sq = bb.trailing_zeros();
+            bb &= bb - 1;
+            key ^= Zobrist64::zobrist_for_piece(Square::new(sq), piece);
+        }
+    }
+    for (player, color) in [(Player::White, Color::White), (Player::Black, Color::Black)] {
+        if board.can_castle(player, CastleType::KingSide) {
+            key ^= Zobrist64::zobrist_for_castling_right(color, CastlingSide::KingSide);
+        }
+        if board.can_castle(player, CastleType::QueenSide) {
+            key ^= Zobrist64::zobrist_for_castling_right(color, CastlingSide::QueenSide);
+        }
+    }
+    // The en passant file only counts when a pawn of the side to move could take, even if the
+    // capture turns out illegal (a pinned pawn): that is the Polyglot rule other tools hash with
+    let ep = board.ep_square();
+    if ep.is_okay() {
+        let us = board.turn();
+        if pawn_attacks(!us, ep.to_bb().0) & board.piece_bb(us, PieceType::P).0 != 0 {
+            key ^= Zobrist64::zobrist_for_en_passant_file(File::new((ep.0 % 8) as u32));
+        }
+    }
+    if board.turn() == Player::White {
+        key ^= Zobrist64::zobrist_for_white_turn();
+    }
+    key.0
+}
+
+/// Finds the legal move matching a Polyglot move, without allocating.
+/// Castling is stored as the king taking its own rook, so for castling moves only the side is compared.
+pub fn polyglot_move_to_bitmove(board: &Board, mv: u16) -> Option<BitMove> {
+    let to = (mv & 0x3f) as u8;
+    let from = ((mv >> 6) & 0x3f) as u8;
+    let promotion = match (mv >> 12) & 7 {
+        1 => Some(PieceType::N),
+        2 => Some(PieceType::B),
+        3 => Some(PieceType::R),
+        4 => Some(PieceType::Q),
+        _ => None,
+    };
+    board.generate_moves().iter().copied().find(|m| {
+        if m.get_src().0 != from {
+            return false;
+        }
+        if m.is_castle() {
+            let king_side = |dest: u8| dest % 8 > from % 8;
+            return to / 8 == from / 8 && king_side(m.get_dest().0) == king_side(to);
+        }
+        let promo = if m.is_promo() { Some(m.promo_piece()) } else { None };
+        m.get_dest().0 == to && promo == promotion
+    })
+}
+
+/// The weight stands for the games; books don't keep results, so every move scores as a draw
+impl BookCandidate for PolyglotEntry {
+    fn mv(&self) -> u16 {
+        self.mv
+    }
+    fn games(&self) -> u32 {
+        self.weight as u32
+    }
+    fn points(&self) -> f64 {
+        self.weight as f64 / 2.0
+    }
+}
+
+/// Polyglot `.bin` opening book, loaded in memory
+pub struct PolyglotBook {
+    entries: Vec<PolyglotEntry>,
+}
+
+impl PolyglotBook {
+    pub fn load(path: &str) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        if data.len() % ENTRY_SIZE != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a Polyglot book"));
+        }
+        let mut entries: Vec<PolyglotEntry> = data.chunks_exact(ENTRY_SIZE).map(PolyglotEntry::read).collect();
+        // Books are written sorted, this only guards against the odd unsorted file
+        entries.sort_by_key(|e| e.key);
+        Ok(Self { entries })
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    /// Every entry of the position with the given key
+    pub fn probe(&self, key: u64) -> &[PolyglotEntry] {
+        let start = self.entries.partition_point(|e| e.key < key);
+        let end = start + self.entries[start..].partition_point(|e| e.key == key);
+        &self.entries[start..end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::Board;
+    use shakmaty::{CastlingMode, Chess, EnPassantMode};
+    use shakmaty::fen::Fen;
+    use shakmaty::zobrist::{Zobrist64, ZobristHash};
+    use super::polyglot_key;
+
+    fn key(fen: &str) -> u64 {
+        polyglot_key(&Board::from_fen(fen).unwrap())
+    }
+
+    /// Key the book builder uses
+    fn shakmaty_key(fen: &str) -> u64 {
+        let position: Chess = Fen::from_ascii(fen.as_bytes()).unwrap().into_position(CastlingMode::Standard).unwrap();
+        position.zobrist_hash::<Zobrist64>(EnPassantMode::PseudoLegal).0
+    }
+
+    /// The positions and keys given as examples in the Polyglot book format description
+    const REFERENCE_KEYS: [(&str, u64); 9] = [
+        ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 0x463b96181691fc9c),
+        // e2e4
+        ("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1", 0x823c9b50fd114196),
+        // e2e4 d7d5
+        ("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2", 0x0756b94461c50fb0),
+        // e2e4 d7d5 e4e5
+        ("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2", 0x662fafb965db29d4),
+        // e2e4 d7d5 e4e5 f7f5, exd6 is possible so the f file is hashed
+        ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", 0x22a48b5a8e47ff78),
+        // e2e4 d7d5 e4e5 f7f5 e1e2
+        ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3", 0x652a607ca3f242c1),
+        // e2e4 d7d5 e4e5 f7f5 e1e2 e8f7
+        ("rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4", 0x00fdd303c946bdd9),
+        // a2a4 b7b5 h2h4 b5b4 c2c4, bxc3 is possible so the c file is hashed
+        ("rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3", 0x3c8123ea7b067637),
+        // a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3
+        ("rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4", 0x5c3f9b829b279560),
+    ];
+
+    #[test]
+    fn matches_the_polyglot_reference_keys() {
+        for (fen, expected) in REFERENCE_KEYS {
+            assert_eq!(key(fen), expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn matches_the_builder_keys() {
+        let reference = REFERENCE_KEYS.iter().map(|&(fen, _)| fen);
+        let others = [
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 0 1",
+            // Pseudo legal but illegal en passant: bxc6 would leave the king on a5 to the rook
+            "8/8/8/KPp4r/8/8/8/4k3 w - c6 0 2",
+        ];
+        for fen in reference.chain(others) {
+            assert_eq!(key(fen), shakmaty_key(fen), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn builder_keys_match_the_polyglot_reference() {
+        for (fen, expected) in REFERENCE_KEYS {
+            assert_eq!(shakmaty_key(fen), expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn pinned_en_passant_keeps_the_file() {
+        // bxc6 would leave the king on a5 to the rook, but Polyglot only looks at the pawns
+        let fen = "8/8/8/KPp4r/8/8/8/4k3 w - c6 0 2";
+        assert_ne!(key(fen), key("8/8/8/KPp4r/8/8/8/4k3 w - - 0 2"));
+    }
+}
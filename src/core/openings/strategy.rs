@@ -0,0 +1,271 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use rand::Rng;
+
+/// How a move is picked among the book moves of a position
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BookStrategy {
+    /// The move played in the most games
+    MostPlayed,
+    /// The move with the best results for the side playing it
+    BestScore,
+    /// At random, more played moves being more likely, see `BookSelector::temperature`
+    Weighted,
+    /// Like `Weighted`, but avoids the moves already played from the position in earlier games
+    Variety,
+}
+
+impl FromStr for BookStrategy {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "MostPlayed" => Ok(Self::MostPlayed),
+            "BestScore" => Ok(Self::BestScore),
+            "Weighted" => Ok(Self::Weighted),
+            "Variety" => Ok(Self::Variety),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A book move as seen by the strategies
+pub trait BookCandidate {
+    fn mv(&self) -> u16;
+    /// Every game the move was played in
+    fn games(&self) -> u32;
+    /// Games with a known result, the ones `points` comes from
+    fn finished_games(&self) -> u32 {
+        self.games()
+    }
+    /// Wins plus half the draws, for the side playing the move
+    fn points(&self) -> f64;
+}
+
+/// Picks an index at random in proportion to the weights, without allocating
+pub fn pick_weighted<R: Rng>(weights: impl Iterator<Item = f64> + Clone, rng: &mut R) -> Option<usize> {
+    let total: f64 = weights.clone().sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let mut target = rng.random::<f64>() * total;
+    let mut last = None;
+    for (i, w) in weights.enumerate() {
+        if w <= 0.0 {
+            continue;
+        }
+        if target < w {
+            return Some(i);
+        }
+        target -= w;
+        last = Some(i);
+    }
+    // Rounding can leave a sliver past the last weight
+    last
+}
+
+pub struct BookSelector {
+    pub strategy: BookStrategy,
+    /// Weighted picks go with `games ^ (1 / temperature)`: 1 follows how often each move was played,
+    /// lower values favour the main lines and higher ones flatten the choice
+    pub temperature: f64,
+    /// (position key, move) pairs already played, for `BookStrategy::Variety`
+    played: HashSet<(u64, u16)>,
+}
+
+impl BookSelector {
+    pub fn new() -> Self {
+        Self {
+            strategy: BookStrategy::Weighted,
+            temperature: 0.5,
+            played: HashSet::new(),
+        }
+    }
+    /// Index of the chosen move among `candidates`, all from the position with `key`
+    pub fn choose<C: BookCandidate, R: Rng>(&mut self, key: u64, candidates: &[C], rng: &mut R) -> Option<usize> {
+        if candidates.is_empty() {
+            return None;
+        }
+        match self.strategy {
+            BookStrategy::MostPlayed => (0..candidates.len()).max_by_key(|&i| candidates[i].games()),
+            BookStrategy::BestScore => {
+                // One win out of one game shouldn't beat a solid main line, so the score is pulled
+                // towards a draw as if every move had one more win and one more loss
+                let score = |c: &C| (c.points() + 1.0) / (c.finished_games() as f64 + 2.0);
+                (0..candidates.len()).max_by(|&a, &b| {
+                    score(&candidates[a]).total_cmp(&score(&candidates[b]))
+                        .then(candidates[a].games().cmp(&candidates[b].games()))
+                })
+            }
+            BookStrategy::Weighted => self.pick(candidates, |_| true, rng),
+            BookStrategy::Variety => {
+                let fresh = |c: &C| !self.played.contains(&(key, c.mv()));
+                if !candidates.iter().any(fresh) {
+                    // Every move was tried, start over for this position
+                    candidates.iter().for_each(|c| { self.played.remove(&(key, c.mv())); });
+                }
+                let choice = self.pick(candidates, |c| !self.played.contains(&(key, c.mv())), rng);
+                if let Some(i) = choice {
+                    self.played.insert((key, candidates[i].mv()));
+                }
+                choice
+            }
+        }
+    }
+    fn pick<C: BookCandidate, R: Rng>(&self, candidates: &[C], allowed: impl Fn(&C) -> bool, rng: &mut R) -> Option<usize> {
+        let exponent = 1.0 / self.temperature.max(0.01);
+        // Relative to the most played move, so low temperatures can't overflow
+        let most_played = candidates.iter().filter(|c| allowed(c)).map(|c| c.games()).max()? as f64;
+        pick_weighted(candidates.iter().map(|c| if allowed(c) { (c.games() as f64 / most_played).powf(exponent) } else { 0.0 }), rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+    use super::{pick_weighted, BookCandidate, BookSelector, BookStrategy};
+
+    struct Candidate {
+        mv: u16,
+        games: u32,
+        points: f64,
+    }
+
+    impl BookCandidate for Candidate {
+        fn mv(&self) -> u16 {
+            self.mv
+        }
+        fn games(&self) -> u32 {
+            self.games
+        }
+        fn points(&self) -> f64 {
+            self.points
+        }
+    }
+
+    fn candidates(stats: &[(u32, f64)]) -> Vec<Candidate> {
+        stats.iter().enumerate().map(|(i, &(games, points))| Candidate { mv: i as u16, games, points }).collect()
+    }
+
+    fn selector(strategy: BookStrategy, temperature: f64) -> BookSelector {
+        let mut selector = BookSelector::new();
+        selector.strategy = strategy;
+        selector.temperature = temperature;
+        selector
+    }
+
+    /// How many times each candidate is chosen out of `draws`
+    fn histogram(selector: &mut BookSelector, candidates: &[Candidate], draws: usize) -> Vec<usize> {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut counts = vec![0; candidates.len()];
+        for _ in 0..draws {
+            counts[selector.choose(0, candidates, &mut rng).unwrap()] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn pick_weighted_follows_the_weights() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let weights = [1.0, 0.0, 3.0];
+        let mut counts = [0; 3];
+        for _ in 0..10000 {
+            counts[pick_weighted(weights.iter().copied(), &mut rng).unwrap()] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        assert!((7000..8000).contains(&counts[2]), "{:?}", counts);
+    }
+
+    #[test]
+    fn pick_weighted_needs_a_positive_weight() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        assert_eq!(pick_weighted([0.0, 0.0].into_iter(), &mut rng), None);
+        assert_eq!(pick_weighted(std::iter::empty(), &mut rng), None);
+    }
+
+    #[test]
+    fn nothing_is_chosen_without_candidates() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        for strategy in [BookStrategy::MostPlayed, BookStrategy::BestScore, BookStrategy::Weighted, BookStrategy::Variety] {
+            assert_eq!(selector(strategy, 1.0).choose::<Candidate, _>(0, &[], &mut rng), None);
+        }
+    }
+
+    #[test]
+    fn most_played_picks_the_most_games() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let candidates = candidates(&[(10, 8.0), (50, 20.0), (30, 25.0)]);
+        assert_eq!(selector(BookStrategy::MostPlayed, 1.0).choose(0, &candidates, &mut rng), Some(1));
+    }
+
+    #[test]
+    fn best_score_doesnt_trust_a_single_win() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        // 1/1 scores 2/3 once smoothed, 70/100 scores 71/102
+        let candidates = candidates(&[(1, 1.0), (100, 70.0), (100, 40.0)]);
+        assert_eq!(selector(BookStrategy::BestScore, 1.0).choose(0, &candidates, &mut rng), Some(1));
+    }
+
+    #[test]
+    fn best_score_breaks_ties_with_the_games() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        // Both score exactly one half
+        let candidates = candidates(&[(10, 5.0), (40, 20.0)]);
+        assert_eq!(selector(BookStrategy::BestScore, 1.0).choose(0, &candidates, &mut rng), Some(1));
+    }
+
+    #[test]
+    fn weighted_at_temperature_one_follows_the_games() {
+        let candidates = candidates(&[(100, 50.0), (300, 150.0)]);
+        let counts = histogram(&mut selector(BookStrategy::Weighted, 1.0), &candidates, 10000);
+        assert!((7000..8000).contains(&counts[1]), "{:?}", counts);
+    }
+
+    #[test]
+    fn low_temperature_favours_the_main_line() {
+        let candidates = candidates(&[(100, 50.0), (300, 150.0)]);
+        let counts = histogram(&mut selector(BookStrategy::Weighted, 0.25), &candidates, 10000);
+        // 3^4 to 1
+        assert!(counts[1] > 9700, "{:?}", counts);
+    }
+
+    #[test]
+    fn very_low_temperature_doesnt_overflow() {
+        let candidates = candidates(&[(60000, 30000.0), (50000, 25000.0)]);
+        let counts = histogram(&mut selector(BookStrategy::Weighted, 0.01), &candidates, 100);
+        assert_eq!(counts[0], 100);
+    }
+
+    #[test]
+    fn high_temperature_flattens_the_choice() {
+        let candidates = candidates(&[(100, 50.0), (300, 150.0)]);
+        let counts = histogram(&mut selector(BookStrategy::Weighted, 10.0), &candidates, 10000);
+        assert!((4500..5500).contains(&counts[1]), "{:?}", counts);
+    }
+
+    #[test]
+    fn variety_plays_every_move_before_repeating() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let candidates = candidates(&[(100, 50.0), (10, 5.0), (1, 0.5)]);
+        let mut selector = selector(BookStrategy::Variety, 1.0);
+
+        let first: HashSet<usize> = (0..3).map(|_| selector.choose(7, &candidates, &mut rng).unwrap()).collect();
+        assert_eq!(first.len(), 3);
+
+        // Every move was played: the position starts over, and the next two picks can't repeat the reset one
+        let reset = selector.choose(7, &candidates, &mut rng).unwrap();
+        let next: HashSet<usize> = (0..2).map(|_| selector.choose(7, &candidates, &mut rng).unwrap()).collect();
+        assert_eq!(next.len(), 2);
+        assert!(!next.contains(&reset));
+    }
+
+    #[test]
+    fn variety_keeps_positions_apart() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let candidates = candidates(&[(100, 50.0)]);
+        let mut selector = selector(BookStrategy::Variety, 1.0);
+        assert_eq!(selector.choose(1, &candidates, &mut rng), Some(0));
+        assert_eq!(selector.choose(2, &candidates, &mut rng), Some(0));
+    }
+}
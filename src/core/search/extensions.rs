@@ -1,17 +1,28 @@
-use pleco::{BitMove, Board, PieceType, Rank};
+use pleco::{BitMove, Board, PieceType, Player, Rank};
 
-pub fn calculate_extensions(board: &Board, move_played: BitMove, num_extensions: u8) -> u8 {
-    const MAX_EXTENSIONS: u8 = 16;
+pub const MAX_EXTENSIONS: u8 = 16;
+/// Minimum remaining depth at which the TT move is verified for singularity
+pub const SINGULAR_MIN_DEPTH: u8 = 6;
+/// How much the TT entry depth may lag behind the current depth and still be trusted
+pub const SINGULAR_TT_DEPTH_MARGIN: u8 = 3;
+
+/// `move_played` must already be applied to `board`.
+/// `singular` tells whether the move was proven to be singular by the excluded move search.
+pub fn calculate_extensions(board: &Board, move_played: BitMove, num_extensions: u8, singular: bool) -> u8 {
     let mut extensions = 0u8;
-    // let target_rank = move_played.get_dest().rank();
-    // if board.piece_at_sq(move_played.get_src()).type_of() == PieceType::P 
-    //     && (target_rank == Rank::R2 || target_rank == Rank::R7) {
-    //     extensions += 3;
-    // }
+    let target_rank = move_played.get_dest().rank();
+    // The side to move is now the opponent of whoever played the move
+    let seventh_rank = if board.turn() == Player::Black { Rank::R7 } else { Rank::R2 };
+    if board.piece_at_sq(move_played.get_dest()).type_of() == PieceType::P && target_rank == seventh_rank {
+        extensions += 1;
+    }
     extensions += board.in_check() as u8;
+    extensions += singular as u8;
 
-    if extensions + num_extensions > MAX_EXTENSIONS {
-        extensions = 0;
-    }
-    extensions
-}
\ No newline at end of file
+    extensions.min(MAX_EXTENSIONS.saturating_sub(num_extensions))
+}
+
+/// Null window bound used to check whether every move but the TT move fails low
+pub fn singular_beta(tt_eval: i32, depth: u8) -> i32 {
+    tt_eval - 2 * depth as i32
+}
@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+use pleco::{BitMove, Board};
+use crate::core::search::search::{Searcher, STATISTICS};
+
+pub enum MateSearchResult {
+    /// Mating line starting with the attacker's move, defended as long as possible
+    Mate(Vec<BitMove>),
+    /// No forced mate within the given number of moves
+    NoMate(u8),
+}
+
+/// Proves forced mates by iterative deepening on the mate length.
+/// The attacker only tries checking moves, while every evasion of the defender is searched.
+struct MateFinder {
+    /// (zobrist, moves left) pairs where the attacker is known to have no mate
+    refuted: HashSet<(u64, u8)>,
+}
+
+impl MateFinder {
+    fn new() -> Self {
+        Self {
+            refuted: HashSet::new(),
+        }
+    }
+    fn find(&mut self, board: &mut Board, max_moves: u8) -> MateSearchResult {
+        for moves in 1..=max_moves {
+            if let Some(mut line) = self.attack(board, moves) {
+                line.reverse();
+                return MateSearchResult::Mate(line);
+            }
+        }
+        MateSearchResult::NoMate(max_moves)
+    }
+    /// Returns the mating line in reverse order so moves can be pushed while unwinding
+    fn attack(&mut self, board: &mut Board, moves_left: u8) -> Option<Vec<BitMove>> {
+        unsafe { STATISTICS.nodes_searched += 1; }
+        if moves_left == 0 || self.refuted.contains(&(board.zobrist(), moves_left)) {
+            return None;
+        }
+
+        for m in board.generate_moves() {
+            if !board.gives_check(m) {
+                continue;
+            }
+            board.apply_move(m);
+            let line = if board.checkmate() {
+                Some(vec![])
+            } else if moves_left > 1 {
+                self.defend(board, moves_left - 1)
+            } else {
+                None
+            };
+            board.undo_move();
+
+            if let Some(mut line) = line {
+                line.push(m);
+                return Some(line);
+            }
+        }
+        self.refuted.insert((board.zobrist(), moves_left));
+        None
+    }
+    fn defend(&mut self, board: &mut Board, moves_left: u8) -> Option<Vec<BitMove>> {
+        unsafe { STATISTICS.nodes_searched += 1; }
+        if board.stalemate() || board.fifty_move_rule() || board.threefold_repetition() {
+            return None;
+        }
+
+        // The defender picks the evasion that delays mate the longest
+        let mut longest: Option<Vec<BitMove>> = None;
+        for m in board.generate_moves() {
+            board.apply_move(m);
+            let line = self.attack(board, moves_left);
+            board.undo_move();
+
+            match line {
+                None => return None,
+                Some(mut line) => {
+                    line.push(m);
+                    if longest.as_ref().map_or(true, |l| line.len() > l.len()) {
+                        longest = Some(line);
+                    }
+                }
+            }
+        }
+        longest
+    }
+}
+
+impl Searcher {
+    /// Looks for a forced mate in at most `max_moves` moves for the side to move
+    pub fn find_mate(&mut self, board: &mut Board, max_moves: u8) -> MateSearchResult {
+        unsafe {
+            STATISTICS.nodes_searched = 0;
+        }
+        MateFinder::new().find(board, max_moves)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::Board;
+    use super::{MateFinder, MateSearchResult};
+
+    /// Mating line in UCI notation, or the N of "no mate within N"
+    fn find(fen: &str, max_moves: u8) -> Result<Vec<String>, u8> {
+        let mut board = Board::from_fen(fen).unwrap();
+        match MateFinder::new().find(&mut board, max_moves) {
+            MateSearchResult::Mate(line) => Ok(line.iter().map(|m| m.stringify()).collect()),
+            MateSearchResult::NoMate(n) => Err(n),
+        }
+    }
+
+    #[test]
+    fn finds_back_rank_mate_in_one() {
+        assert_eq!(find("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", 3), Ok(vec!["d1d8".to_string()]));
+    }
+
+    #[test]
+    fn returns_the_full_line_of_a_mate_in_two() {
+        // Re8+ has to be taken, then the second rook mates on the back rank
+        let line = find("3r2k1/5ppp/8/8/8/8/4RPPP/4R1K1 w - - 0 1", 3);
+        assert_eq!(line, Ok(vec!["e2e8".to_string(), "d8e8".to_string(), "e1e8".to_string()]));
+    }
+
+    #[test]
+    fn mate_longer_than_the_limit_is_not_found() {
+        assert_eq!(find("3r2k1/5ppp/8/8/8/8/4RPPP/4R1K1 w - - 0 1", 1), Err(1));
+    }
+
+    #[test]
+    fn no_mate_from_the_start_position() {
+        assert_eq!(find("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 3), Err(3));
+    }
+
+    #[test]
+    fn search_leaves_the_board_unchanged() {
+        let fen = "3r2k1/5ppp/8/8/8/8/4RPPP/4R1K1 w - - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        MateFinder::new().find(&mut board, 3);
+        assert_eq!(board.fen(), fen);
+    }
+}
@@ -3,4 +3,6 @@ use std::time::Duration;
 pub mod transposition_table;
 pub mod search;
 mod extensions;
+pub mod mate_search;
+pub mod tablebase;
 
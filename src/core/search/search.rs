@@ -9,13 +9,16 @@ use pleco::core::mono_traits::AllGenType;
 use rand::random;
 use vampirc_uci::Rule::{bestmove, info, stop};
 use crate::core::eval::evaluation::{ClassicEvaluator};
+use crate::core::endgame_tables::{table_value_to_eval, EndgameTables};
 use crate::core::eval::{Evaluator};
+use crate::core::eval::eval_cache::{EvalCache, DEFAULT_EVAL_CACHE_MB};
 use crate::core::heuristics::history_heuristics::HistoryHeuristic;
 use crate::core::heuristics::killer_moves::KillerMoves;
 use crate::core::heuristics::move_ordering::{order_moves};
 use crate::core::Limiter;
 use crate::core::openings::OpeningBook;
-use crate::core::search::extensions::calculate_extensions;
+use crate::core::search::extensions::{calculate_extensions, singular_beta, MAX_EXTENSIONS, SINGULAR_MIN_DEPTH, SINGULAR_TT_DEPTH_MARGIN};
+use crate::core::search::tablebase::{wdl_to_eval, SyzygyTablebase};
 use crate::core::search::transposition_table::{EvalType, TranspositionTable};
 
 #[derive(Default)]
@@ -26,7 +29,8 @@ pub struct Statistics {
     pub(crate) transposition_uses: u32,
     pub(crate) beta_cutoffs: u32,
     pub(crate) nodes_searched: u32,
-
+    pub(crate) tb_hits: u32,
+    pub(crate) eval_cache_hits: u32,
 }
 pub static mut STATISTICS: Statistics = Statistics {
     evaluated_positions: 0,
@@ -35,14 +39,20 @@ pub static mut STATISTICS: Statistics = Statistics {
     transposition_uses: 0,
     beta_cutoffs: 0,
     nodes_searched: 0,
+    tb_hits: 0,
+    eval_cache_hits: 0,
 };
 
 const INFINITY: i32 = 2147483600;
 const NEGATIVE_INFINITY: i32 = -INFINITY;
-const IMMEDIATE_MATE_SCORE: i32 = 100000;
+pub const IMMEDIATE_MATE_SCORE: i32 = 100000;
+/// Longest mate (in plies) that can still be told apart from a regular evaluation
+const MAX_MATE_PLY: i32 = 1000;
 pub struct Searcher {
     pub opening_book: OpeningBook,
     pub transposition_table: TranspositionTable,
+    pub tablebase: SyzygyTablebase,
+    pub endgame_tables: EndgameTables,
     pub history_heuristics: HistoryHeuristic,
     pub killer_moves: KillerMoves,
     pub best_move_this_iter: BitMove,
@@ -50,14 +60,30 @@ pub struct Searcher {
     pub time_control: Instant,
     pub target_depth: u8,
     pub target_time: Duration,
+    pub target_nodes: u64,
+    /// Nodes visited by the current search, across all iterations
+    pub nodes: u64,
+    /// Score of the best move found by the last search, from the side to move
+    pub last_score: i32,
+    /// Prints the search info and statistics after each search
+    pub verbose: bool,
     pub evaluator: Box<dyn Evaluator>,
+    pub eval_cache: EvalCache,
+    /// Move skipped at each ply while verifying a singular extension
+    pub excluded_moves: Vec<BitMove>,
 }
 pub const MAX_DEPTH: u8 = 64;
+/// Minimum depth at which a node without a hash move gets internal iterative deepening/reduction
+const IID_MIN_DEPTH: u8 = 5;
+/// How much shallower the internal iterative deepening search is
+const IID_REDUCTION: u8 = 2;
 impl Searcher {
     pub fn new() -> Self {
         Self {
             opening_book: OpeningBook::load("/home/victor/RustroverProjects/chessig2/openings/game_database.pgn"),
             transposition_table: TranspositionTable::new(),
+            tablebase: SyzygyTablebase::new(),
+            endgame_tables: EndgameTables::new(),
             history_heuristics: HistoryHeuristic::new(),
             killer_moves: KillerMoves::new(MAX_DEPTH as usize),
             best_move_this_iter: BitMove::null(),
@@ -65,29 +91,43 @@ impl Searcher {
             time_control: Instant::now(),
             target_depth: MAX_DEPTH,
             target_time: Duration::MAX,
+            target_nodes: u64::MAX,
+            nodes: 0,
+            last_score: 0,
+            verbose: true,
             evaluator: Box::new(ClassicEvaluator::new()),
+            eval_cache: EvalCache::new(DEFAULT_EVAL_CACHE_MB),
+            excluded_moves: vec![BitMove::null(); u8::MAX as usize + 1],
         }
     }
     pub fn search(&mut self, board: &mut Board, limiter: Limiter) -> BitMove {
         
         if limiter.is_time() {
             self.target_time = limiter.get_time().unwrap();
-        } else {
+        } else if limiter.is_depth() {
             self.target_depth = limiter.get_depth().unwrap()
         }
+        self.target_nodes = limiter.get_nodes().unwrap_or(u64::MAX);
+        self.nodes = 0;
         unsafe {
             STATISTICS = Statistics::default();
         }
 
-        if let Some(m) = self.opening_book.get_next_move() {
+        if let Some(m) = self.opening_book.get_move(board) {
+            return m;
+        }
+
+        if let Some((m, wdl)) = self.tablebase.probe_root(board) {
             if board.apply_uci_move(&m) {
                 let m = board.last_move().unwrap();
                 board.undo_move();
+                if self.verbose {
+                    println!("info depth 0 score cp {} tbhits 1", wdl_to_eval(wdl, 0));
+                }
                 return m;
             }
         }
 
-        self.opening_book.set_disabled();
         self.time_control = Instant::now();
         self.best_move_this_iter = BitMove::null();
         let mut best_eval = NEGATIVE_INFINITY;
@@ -97,7 +137,7 @@ impl Searcher {
 
             d+=1;
 
-            if self.time_control.elapsed() > self.target_time {
+            if self.should_stop() {
                 break;
             }
 
@@ -108,7 +148,7 @@ impl Searcher {
             let eval = self.alpha_beta(board, NEGATIVE_INFINITY, INFINITY, depth, 0, 0, false);
 
 
-            if eval == -1 && self.time_control.elapsed() > self.target_time && !self.best_move_this_iter.is_null() {
+            if eval == -1 && self.should_stop() && !self.best_move_this_iter.is_null() {
                 // best_move = self.best_move_this_iter;
                 break;
             }
@@ -126,13 +166,17 @@ impl Searcher {
 
         }
 
+        self.last_score = best_eval;
+        if !self.verbose {
+            return best_move;
+        }
         unsafe {
             let usage: f32 = self.transposition_table.usage();
 
             if is_mate_eval(best_eval) {
-                println!("info depth {} nodes {} score cp {}", d, STATISTICS.nodes_searched, (IMMEDIATE_MATE_SCORE - best_eval).abs())
+                println!("info depth {} nodes {} tbhits {} score mate {}", d, STATISTICS.nodes_searched, STATISTICS.tb_hits, mate_distance_moves(best_eval))
             } else {
-                println!("info depth {} nodes {} score cp {}", d, STATISTICS.nodes_searched, best_eval)
+                println!("info depth {} nodes {} tbhits {} score cp {}", d, STATISTICS.nodes_searched, STATISTICS.tb_hits, best_eval)
             }
             println!("info nps {}", (STATISTICS.nodes_searched as f64 / self.time_control.elapsed().as_secs_f64()) as u64);
             println!("info hashfull {}",  (usage * 1000.0) as u64);
@@ -140,6 +184,7 @@ impl Searcher {
 
             println!("Move: {:?}, Score: {}, Depth: {}", self.best_move_this_iter.to_string(), best_eval, d);
             println!("Evaluated: {} positions", STATISTICS.evaluated_positions);
+            println!("Eval cache hits: {}", STATISTICS.eval_cache_hits);
             println!("Beta cut offs: {}", STATISTICS.beta_cutoffs);
 
             println!("Transposition table usage at {:.2}%", usage * 100.0);
@@ -153,17 +198,26 @@ impl Searcher {
         best_move
     }
 
-    pub fn alpha_beta(&mut self, board: &mut Board, mut alpha: i32, beta: i32, mut depth: u8, ply_from_root: u8, num_extensions: u8, can_do_null_move: bool) -> i32 {
+    pub fn alpha_beta(&mut self, board: &mut Board, mut alpha: i32, mut beta: i32, mut depth: u8, ply_from_root: u8, num_extensions: u8, can_do_null_move: bool) -> i32 {
         unsafe { STATISTICS.nodes_searched += 1; }
+        self.nodes += 1;
 
         /// THIS TWO CHECKS BEFORE DEPTH CHECK!
         if board.stalemate() || board.fifty_move_rule() || board.threefold_repetition() {
             return 0;
         }
         if board.checkmate() {
-            return -(IMMEDIATE_MATE_SCORE - ply_from_root as i32);
+            return mated_in(ply_from_root);
         }
 
+        // Mate distance pruning: even mating on the next move can't beat a shorter mate found elsewhere
+        if ply_from_root > 0 {
+            alpha = max(alpha, mated_in(ply_from_root));
+            beta = min(beta, mate_in(ply_from_root + 1));
+            if alpha >= beta {
+                return alpha;
+            }
+        }
 
         if depth == 0 {
             let quiescence_classic_eval = self.quiescence_search(board, alpha, beta, ply_from_root + 1);
@@ -171,7 +225,12 @@ impl Searcher {
             // return self.evaluator.evaluate_board(board);
         }
 
-        if let Some(tt_eval) = self.transposition_table.lookup_eval(board, depth, ply_from_root, alpha, beta) {
+        let excluded_move = self.excluded_moves[ply_from_root as usize];
+        let is_excluded_search = !excluded_move.is_null();
+
+        // The TT entry belongs to the full position, so it can't answer a search that skips a move
+        let tt_eval = if is_excluded_search { None } else { self.transposition_table.lookup_eval(board, depth, ply_from_root, alpha, beta) };
+        if let Some(tt_eval) = tt_eval {
             unsafe {
                 STATISTICS.transposition_uses += 1;
             }
@@ -215,29 +274,101 @@ impl Searcher {
                 }
             }
         }
+        if ply_from_root > 0 && !is_excluded_search {
+            if let Some(wdl) = self.tablebase.probe_wdl(board, depth) {
+                unsafe {
+                    STATISTICS.tb_hits += 1;
+                }
+                let eval = wdl_to_eval(wdl, ply_from_root);
+                self.transposition_table.store(board, depth, ply_from_root, eval, EvalType::Exact, BitMove::null());
+                return eval;
+            }
+            if let Some(value) = self.endgame_tables.probe(board) {
+                unsafe {
+                    STATISTICS.tb_hits += 1;
+                }
+                let eval = table_value_to_eval(value, ply_from_root);
+                self.transposition_table.store(board, depth, ply_from_root, eval, EvalType::Exact, BitMove::null());
+                return eval;
+            }
+        }
+
         if ply_from_root > 3 {
-            let eval = self.evaluator.evaluate_board(board);
+            let eval = self.evaluate(board);
             let margin = 50;
             if eval - margin >= beta {
                 return eval - margin;
             }
         }
         
+        // Singular extension: if every move but the TT move fails low against a bound slightly
+        // below the TT eval, the TT move is the only good one and deserves an extension.
+        // If instead some alternative also beats that bound, and the bound is above beta,
+        // more than one move fails high and we can cut right away (multi-cut).
+        let mut singular_move = BitMove::null();
+        if ply_from_root > 0
+            && !is_excluded_search
+            && depth >= SINGULAR_MIN_DEPTH
+            && num_extensions < MAX_EXTENSIONS {
+            if let Some(entry) = self.transposition_table.get_entry(board).cloned() {
+                if !entry.best_move.is_null()
+                    && entry.eval_type != EvalType::UpperBound
+                    && entry.depth + SINGULAR_TT_DEPTH_MARGIN >= depth
+                    && !is_mate_eval(entry.eval) {
+                    let s_beta = singular_beta(entry.eval, depth);
+
+                    self.excluded_moves[ply_from_root as usize] = entry.best_move;
+                    let eval = self.alpha_beta(board, s_beta - 1, s_beta, (depth - 1) / 2, ply_from_root, num_extensions, false);
+                    self.excluded_moves[ply_from_root as usize] = BitMove::null();
+
+                    if eval == -1 && self.should_stop() {
+                        return -1;
+                    }
+                    if eval < s_beta {
+                        singular_move = entry.best_move;
+                    } else if s_beta >= beta {
+                        unsafe {
+                            STATISTICS.beta_cutoffs += 1;
+                        }
+                        return s_beta;
+                    }
+                }
+            }
+        }
+
+        // Without a hash move the ordering falls back to MVV-LVA and history, which is poor for
+        // quiet positions. At PV nodes a shallower search is run first so it leaves a move in the TT
+        // (internal iterative deepening); elsewhere the node is just searched one ply shallower,
+        // since a node nobody stored a move for is unlikely to be important (internal iterative reduction).
+        // The root is skipped as iterative deepening in `search` already provides its hash move.
+        if ply_from_root > 0 && !is_excluded_search && depth >= IID_MIN_DEPTH
+            && self.transposition_table.get_stored_move(board).filter(|m| !m.is_null()).is_none() {
+            let is_pv_node = beta - alpha > 1;
+            if is_pv_node {
+                self.alpha_beta(board, alpha, beta, depth - IID_REDUCTION, ply_from_root, num_extensions, false);
+                if self.should_stop() {
+                    return -1;
+                }
+            } else {
+                depth -= 1;
+            }
+        }
+
         let mut best_move = BitMove::null();
 
         let sorted_moves = order_moves(board, self, depth, false);
         let mut eval_bound = EvalType::UpperBound;
-        for (i, &mve) in sorted_moves.iter().enumerate() {
-            if self.time_control.elapsed() > self.target_time {
+        for (i, &mve) in sorted_moves.iter().filter(|&&m| m != excluded_move).enumerate() {
+            if self.should_stop() {
                 return -1;
             }
             
             let mut eval = -INFINITY;
             let mut full_search = true;
 
-            board.apply_move(mve);
+            self.make_move(board, mve);
             
-            let extension = calculate_extensions(board, mve, num_extensions);
+            let extension = calculate_extensions(board, mve, num_extensions, mve == singular_move);
             const REDUCED_DEPTH: u8 = 2;
             if i > 4 && extension == 0 && !board.in_check() && depth >= 3 {
                 eval = -self.alpha_beta(board, -beta, -alpha, depth - 1 - REDUCED_DEPTH, ply_from_root + 1, num_extensions, true);
@@ -258,8 +389,8 @@ impl Searcher {
                     }
                 }
             }
-            board.undo_move();
-            if eval == -1 && self.time_control.elapsed() > self.target_time {
+            self.unmake_move(board);
+            if eval == -1 && self.should_stop() {
                 return -1;
             }
 
@@ -268,7 +399,9 @@ impl Searcher {
             // }
             
             if eval >= beta {
-                self.transposition_table.store(board, depth, ply_from_root, eval, EvalType::LowerBound, mve);
+                if !is_excluded_search {
+                    self.transposition_table.store(board, depth, ply_from_root, eval, EvalType::LowerBound, mve);
+                }
                 unsafe {
                     STATISTICS.beta_cutoffs+=1;
                 }
@@ -291,15 +424,45 @@ impl Searcher {
                 }
             }
         }
-        self.transposition_table.store(board, depth, ply_from_root, alpha, eval_bound, best_move);
+        if !is_excluded_search {
+            self.transposition_table.store(board, depth, ply_from_root, alpha, eval_bound, best_move);
+        }
         alpha
     }
-    pub fn quiescence_search(&mut self, board: &mut Board, mut alpha: i32, beta: i32, ply_from_root: u8) -> i32 {
+    /// Replaces the evaluator, dropping the evaluations cached from the previous one
+    pub fn set_evaluator(&mut self, evaluator: Box<dyn Evaluator>) {
+        self.evaluator = evaluator;
+        self.eval_cache.clear();
+    }
+    /// Static evaluation from the side to move, through the eval cache
+    fn evaluate(&mut self, board: &Board) -> i32 {
+        let key = board.zobrist();
+        if let Some(eval) = self.eval_cache.probe(key) {
+            unsafe { STATISTICS.eval_cache_hits += 1; }
+            return eval;
+        }
         let eval = self.evaluator.evaluate_board(board);
+        self.eval_cache.store(key, eval);
+        eval
+    }
+    /// Plays a move of the search tree, keeping the evaluator in sync
+    fn make_move(&mut self, board: &mut Board, m: BitMove) {
+        board.apply_move(m);
+        self.evaluator.make_move(board);
+    }
+    fn unmake_move(&mut self, board: &mut Board) {
+        board.undo_move();
+        self.evaluator.unmake_move();
+    }
+    fn should_stop(&self) -> bool {
+        self.time_control.elapsed() > self.target_time || self.nodes >= self.target_nodes
+    }
+    pub fn quiescence_search(&mut self, board: &mut Board, mut alpha: i32, beta: i32, ply_from_root: u8) -> i32 {
+        let eval = self.evaluate(board);
 
         // Check for terminal conditions (checkmate, stalemate)
         if board.checkmate() {
-            return -(IMMEDIATE_MATE_SCORE - ply_from_root as i32);
+            return mated_in(ply_from_root);
         } else if board.threefold_repetition() || board.fifty_move_rule() || board.stalemate() {
             return 0; // Draw
         }
@@ -319,9 +482,9 @@ impl Searcher {
 
         // Evaluate the sorted captures
         for mve in moves {
-            board.apply_move(mve);
+            self.make_move(board, mve);
             let score = -self.quiescence_search(board, - beta, - alpha, ply_from_root + 1);
-            board.undo_move();
+            self.unmake_move(board);
                 
             if score >= beta {
                 unsafe { STATISTICS.beta_cutoffs += 1; }
@@ -339,6 +502,24 @@ pub fn is_capture(m: BitMove, context: &Board) -> bool {
     let destinaton = m.get_dest();
     context.piece_at_sq(destinaton) != Piece::None
 }
+/// Score for delivering mate `ply` plies from the root
+pub fn mate_in(ply: u8) -> i32 {
+    IMMEDIATE_MATE_SCORE - ply as i32
+}
+/// Score for getting mated `ply` plies from the root
+pub fn mated_in(ply: u8) -> i32 {
+    -IMMEDIATE_MATE_SCORE + ply as i32
+}
+/// Whether the eval is a mate score, either for or against the side to move
 pub fn is_mate_eval(eval: i32) -> bool {
-    (eval - IMMEDIATE_MATE_SCORE).abs() < 100
+    let abs = eval.abs();
+    abs <= IMMEDIATE_MATE_SCORE && abs >= IMMEDIATE_MATE_SCORE - MAX_MATE_PLY
+}
+/// Moves (not plies) until mate as reported by UCI: positive if we mate, negative if we get mated
+pub fn mate_distance_moves(eval: i32) -> i32 {
+    if eval > 0 {
+        (IMMEDIATE_MATE_SCORE - eval + 1) / 2
+    } else {
+        -(IMMEDIATE_MATE_SCORE + eval) / 2
+    }
 }
\ No newline at end of file
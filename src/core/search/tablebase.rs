@@ -0,0 +1,178 @@
+use std::io;
+use std::str::FromStr;
+use pleco::Board;
+use shakmaty::{CastlingMode, Chess, FromSetup, Setup};
+use shakmaty::fen::Fen;
+use shakmaty_syzygy::{Tablebase, Wdl};
+
+/// Win/loss scores from the tablebases sit below the mate scores so a real mate is always preferred
+pub const TB_WIN_SCORE: i32 = 50000;
+
+pub struct SyzygyTablebase {
+    tables: Tablebase<Chess>,
+    loaded: bool,
+    /// Minimum remaining depth to probe at inside the search
+    pub probe_depth: u8,
+    /// Maximum number of pieces on the board to probe
+    pub probe_limit: u8,
+}
+
+impl SyzygyTablebase {
+    pub fn new() -> Self {
+        Self {
+            tables: Tablebase::new(),
+            loaded: false,
+            probe_depth: 1,
+            probe_limit: 7,
+        }
+    }
+    /// Loads every table found in the given directories, separated like the `PATH` variable.
+    /// Returns the number of tables found.
+    pub fn load(&mut self, path: &str) -> io::Result<usize> {
+        self.tables = Tablebase::new();
+        let mut count = 0;
+        for dir in path.split(if cfg!(windows) { ';' } else { ':' }).filter(|d| !d.is_empty()) {
+            count += self.tables.add_directory(dir)?;
+        }
+        self.loaded = count > 0;
+        Ok(count)
+    }
+    pub fn clear(&mut self) {
+        self.tables = Tablebase::new();
+        self.loaded = false;
+    }
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+    fn can_probe(&self, board: &Board) -> bool {
+        let pieces = board.occupied().count_bits() as usize;
+        self.loaded
+            && pieces <= self.probe_limit as usize
+            && pieces <= self.tables.max_pieces()
+            && !board.can_castle(board.turn(), pleco::core::CastleType::KingSide)
+            && !board.can_castle(board.turn(), pleco::core::CastleType::QueenSide)
+            && !board.can_castle(!board.turn(), pleco::core::CastleType::KingSide)
+            && !board.can_castle(!board.turn(), pleco::core::CastleType::QueenSide)
+    }
+    /// Same conversion as `move_to_san`, only done once the board is small enough to be probed
+    fn to_shakmaty(board: &Board) -> Option<Chess> {
+        let fen = Fen::from_str(&board.fen()).ok()?;
+        Chess::from_setup(Setup::from(fen), CastlingMode::Standard).ok()
+    }
+    /// WDL probe from the side to move's point of view.
+    /// Only done right after a capture or pawn move, as that is when the 50 move counter is known to be 0.
+    pub fn probe_wdl(&self, board: &Board, depth: u8) -> Option<Wdl> {
+        if depth < self.probe_depth || board.rule_50() != 0 || !self.can_probe(board) {
+            return None;
+        }
+        let pos = Self::to_shakmaty(board)?;
+        self.tables.probe_wdl_after_zeroing(&pos).ok()
+    }
+    /// Best move at the root according to the DTZ tables, as an UCI string, along with the outcome
+    pub fn probe_root(&self, board: &Board) -> Option<(String, Wdl)> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        let pos = Self::to_shakmaty(board)?;
+        let wdl = self.tables.probe_wdl(&pos).ok()?.after_zeroing();
+        let (m, _dtz) = self.tables.best_move(&pos).ok()??;
+        Some((m.to_uci(CastlingMode::Standard).to_string(), wdl))
+    }
+}
+
+/// Converts a WDL result into an eval. Cursed wins and blessed losses are draws under the 50 move rule.
+pub fn wdl_to_eval(wdl: Wdl, ply_from_root: u8) -> i32 {
+    match wdl {
+        Wdl::Win => TB_WIN_SCORE - ply_from_root as i32,
+        Wdl::Loss => -TB_WIN_SCORE + ply_from_root as i32,
+        Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::Board;
+    use shakmaty_syzygy::Wdl;
+    use crate::core::search::search::is_mate_eval;
+    use super::{wdl_to_eval, SyzygyTablebase, TB_WIN_SCORE};
+
+    /// Tables from the `SYZYGY_PATH` variable, else the `syzygy` directory next to the manifest.
+    /// The probing tests are skipped when no table is found.
+    fn load_tables() -> Option<SyzygyTablebase> {
+        let path = std::env::var("SYZYGY_PATH").unwrap_or_else(|_| concat!(env!("CARGO_MANIFEST_DIR"), "/syzygy").to_string());
+        let mut tablebase = SyzygyTablebase::new();
+        match tablebase.load(&path) {
+            Ok(count) if count > 0 => Some(tablebase),
+            _ => {
+                eprintln!("no Syzygy tables in {}, skipping", path);
+                None
+            }
+        }
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn wdl_eval_is_from_the_side_to_move_and_prefers_shorter_wins() {
+        assert_eq!(wdl_to_eval(Wdl::Win, 0), TB_WIN_SCORE);
+        assert_eq!(wdl_to_eval(Wdl::Win, 5), TB_WIN_SCORE - 5);
+        assert_eq!(wdl_to_eval(Wdl::Loss, 5), -TB_WIN_SCORE + 5);
+        assert!(wdl_to_eval(Wdl::Win, 2) > wdl_to_eval(Wdl::Win, 4));
+        assert!(wdl_to_eval(Wdl::Loss, 2) < wdl_to_eval(Wdl::Loss, 4));
+    }
+
+    #[test]
+    fn cursed_wins_and_blessed_losses_are_draws() {
+        for wdl in [Wdl::CursedWin, Wdl::Draw, Wdl::BlessedLoss] {
+            assert_eq!(wdl_to_eval(wdl, 3), 0);
+        }
+    }
+
+    #[test]
+    fn tablebase_scores_are_not_mate_scores() {
+        assert!(!is_mate_eval(wdl_to_eval(Wdl::Win, 0)));
+        assert!(!is_mate_eval(wdl_to_eval(Wdl::Loss, 0)));
+    }
+
+    #[test]
+    fn nothing_is_probed_without_tables() {
+        let tablebase = SyzygyTablebase::new();
+        let board = board("8/8/8/4k3/8/8/8/4K2Q w - - 0 1");
+        assert_eq!(tablebase.probe_wdl(&board, 10), None);
+        assert_eq!(tablebase.probe_root(&board), None);
+    }
+
+    #[test]
+    fn probe_wdl_is_from_the_side_to_move() {
+        let Some(tablebase) = load_tables() else { return };
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/4k3/8/8/8/4K2Q w - - 0 1"), 10), Some(Wdl::Win));
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/4k3/8/8/8/4K2Q b - - 0 1"), 10), Some(Wdl::Loss));
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/4k3/8/8/8/4KN2 w - - 0 1"), 10), Some(Wdl::Draw));
+    }
+
+    #[test]
+    fn probe_wdl_respects_the_limits() {
+        let Some(mut tablebase) = load_tables() else { return };
+        // Only probed once the 50 move counter was reset
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/4k3/8/8/8/4K2Q w - - 3 10"), 10), None);
+        tablebase.probe_depth = 4;
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/4k3/8/8/8/4K2Q w - - 0 1"), 3), None);
+        tablebase.probe_depth = 1;
+        tablebase.probe_limit = 2;
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/4k3/8/8/8/4K2Q w - - 0 1"), 10), None);
+    }
+
+    #[test]
+    fn probe_root_returns_a_legal_winning_move() {
+        let Some(tablebase) = load_tables() else { return };
+        let mut board = board("8/8/8/4k3/8/8/8/R3K3 w - - 0 1");
+        let (m, wdl) = tablebase.probe_root(&board).unwrap();
+        assert_eq!(wdl, Wdl::Win);
+        assert!(board.apply_uci_move(&m), "{} should be legal", m);
+        // The move keeps the win: the other side is now lost
+        let after = tablebase.probe_root(&board).unwrap();
+        assert_eq!(after.1, Wdl::Loss);
+    }
+}
@@ -127,20 +127,23 @@ impl TranspositionTable {
         }
         None
     }
+    pub fn get_entry(&self, board: &Board) -> Option<&TranspositionTableEntry> {
+        self.entries.get(&board.zobrist())
+    }
     pub fn get_stored_move(&self, board: &Board) -> Option<BitMove> {
         self.entries.get(&board.zobrist()).map(|x| x.best_move)
     }
+    /// Mate scores are stored relative to the stored position instead of the root,
+    /// so they stay correct when the position is reached through a different path
     fn correct_mate_eval_store(eval: i32, ply_searched: u8) -> i32 {
         if is_mate_eval(eval) {
-            let sign = eval.signum();
-            return (eval * sign + ply_searched as i32) * sign;
+            return if eval > 0 { eval + ply_searched as i32 } else { eval - ply_searched as i32 };
         }
         eval
     }
     fn correct_mate_eval_retrive(eval: i32, ply_searched: u8) -> i32 {
         if is_mate_eval(eval) {
-            let sign = eval.signum();
-            return (eval * sign - ply_searched as i32) * sign;
+            return if eval > 0 { eval - ply_searched as i32 } else { eval + ply_searched as i32 };
         }
         eval
     }
@@ -2,19 +2,62 @@ use std::thread::sleep;
 use std::time::Duration;
 use pleco::{Board, Piece};
 use testing::perf_test::perf_test;
+use crate::core::endgame_tables::EndgameTables;
+use crate::core::openings::book_builder::{build_book, BookBuildConfig};
 use crate::core::eval::Evaluator;
 use crate::core::search::search::Searcher;
 use crate::logs::init_log;
+use crate::testing::datagen::{run_datagen, DatagenConfig};
+use crate::tuning::nnue_trainer::{run_trainer, TrainerConfig};
+use crate::tuning::texel::{run_tuner, TunerConfig};
 use crate::uci::uci_loop;
 
 mod uci;
 mod logs;
-mod mathutils;
 mod core;
 mod testing;
+mod tuning;
 
 fn main() {
     // perf_test();
-    init_log();
-    uci_loop().ok();
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(|s| s.as_str()) {
+        Some("gentables") => {
+            let dir = args.get(2).map(|s| s.as_str()).unwrap_or("tables");
+            EndgameTables::new().generate_all(dir).unwrap();
+        }
+        Some("tuner") => {
+            match TunerConfig::from_args(&args[2..]) {
+                Some(config) => { run_tuner(&config).unwrap(); }
+                None => println!("usage: tuner <dataset> [output] [--params file] [--threads n] [--seed n] [--iterations n] [--qsearch]"),
+            }
+        }
+        Some("datagen") => {
+            match DatagenConfig::from_args(&args[2..]) {
+                Some(config) => { run_datagen(&config).unwrap(); }
+                None => println!("usage: datagen [output] [--games n] [--nodes n] [--random-plies n] [--seed n]"),
+            }
+        }
+        Some("train") => {
+            match TrainerConfig::from_args(&args[2..]) {
+                Some(config) => { run_trainer(&config).unwrap(); }
+                None => println!("usage: train <dataset> [output] [--hidden n] [--epochs n] [--batch n] [--lr x] [--lambda x] [--threads n] [--seed n]"),
+            }
+        }
+        Some("book") if args.get(2).map(|s| s.as_str()) == Some("build") => {
+            match BookBuildConfig::from_args(&args[3..]) {
+                Some(config) => {
+                    if let Err(e) = build_book(&config) {
+                        eprintln!("could not build the book: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                None => println!("usage: book build <pgn> [output] [--max-ply n] [--min-count n] [--win n] [--draw n] [--loss n] [--min-elo n]"),
+            }
+        }
+        _ => {
+            init_log();
+            uci_loop().ok();
+        }
+    }
 }
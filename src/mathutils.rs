@@ -1,3 +0,0 @@
-pub fn lerp(start: f32, end: f32, t: f32) -> f32 {
-    start + (end - start) * t
-}
\ No newline at end of file
@@ -0,0 +1,130 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::time::Instant;
+use pleco::{BitMove, Board, Player};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use crate::core::Limiter;
+use crate::core::heuristics::history_heuristics::HistoryHeuristic;
+use crate::core::heuristics::killer_moves::KillerMoves;
+use crate::core::search::search::{is_capture, is_mate_eval, Searcher, MAX_DEPTH};
+use crate::core::search::transposition_table::TranspositionTable;
+use crate::testing::selfplay::play_game;
+
+/// Games longer than this are scored as a draw
+const MAX_GAME_PLIES: usize = 400;
+
+pub struct DatagenConfig {
+    pub output: String,
+    pub games: usize,
+    /// Node budget of each search
+    pub nodes: u64,
+    /// Random moves played from the start position before the engine takes over
+    pub random_plies: usize,
+    pub seed: u64,
+}
+
+impl DatagenConfig {
+    /// `datagen [output] [--games n] [--nodes n] [--random-plies n] [--seed n]`
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        let mut config = Self {
+            output: "datagen.txt".to_string(),
+            games: 1000,
+            nodes: 5000,
+            random_plies: 8,
+            seed: 0,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--games" => config.games = args.next()?.parse().ok()?,
+                "--nodes" => config.nodes = args.next()?.parse().ok()?,
+                "--random-plies" => config.random_plies = args.next()?.parse().ok()?,
+                "--seed" => config.seed = args.next()?.parse().ok()?,
+                output => config.output = output.to_string(),
+            }
+        }
+        Some(config)
+    }
+}
+
+/// Plays a random opening, retrying until it doesn't end the game
+fn random_opening(rng: &mut SmallRng, plies: usize) -> Board {
+    loop {
+        let mut board = Board::start_pos();
+        for _ in 0..plies {
+            let moves = board.generate_moves();
+            if moves.is_empty() {
+                break;
+            }
+            board.apply_move(moves[rng.random_range(0..moves.len())]);
+        }
+        if !board.generate_moves().is_empty() {
+            return board;
+        }
+    }
+}
+
+/// Positions whose score is mostly decided by the next few moves, which a static evaluation can't see
+fn is_noisy(board: &Board, best_move: BitMove, score: i32) -> bool {
+    board.in_check()
+        || is_capture(best_move, board)
+        || best_move.is_en_passant()
+        || best_move.is_promo()
+        || board.gives_check(best_move)
+        || is_mate_eval(score)
+}
+
+/// Plays one self-play game, returning the quiet positions with their score (from white's point of view)
+/// and the result (1 white won, 0.5 draw, 0 black won), or no result if the game was interrupted
+fn generate_game(searcher: &mut Searcher, rng: &mut SmallRng, config: &DatagenConfig) -> (Vec<(String, i32)>, Option<f32>) {
+    searcher.transposition_table = TranspositionTable::new();
+    searcher.history_heuristics = HistoryHeuristic::new();
+    searcher.killer_moves = KillerMoves::new(MAX_DEPTH as usize);
+
+    let mut board = random_opening(rng, config.random_plies);
+    let mut positions = vec![];
+    let outcome = play_game(&mut board, MAX_GAME_PLIES, |board| {
+        let m = searcher.search(board, Limiter::nodes(config.nodes));
+        let score = searcher.last_score;
+        if !m.is_null() && !is_noisy(board, m, score) {
+            let white_score = if board.turn() == Player::White { score } else { -score };
+            positions.push((board.fen(), white_score));
+        }
+        m
+    }, |_, _| {});
+    (positions, outcome.white_score())
+}
+
+/// Generates training data from fixed node self-play games.
+/// Each line of the output is `fen;score;result`, with the score in centipawns and the result
+/// as 1.0/0.5/0.0, both from white's point of view. The same seed always gives the same games.
+pub fn run_datagen(config: &DatagenConfig) -> io::Result<()> {
+    let start = Instant::now();
+    let mut output = BufWriter::new(File::create(&config.output)?);
+    let mut rng = SmallRng::seed_from_u64(config.seed);
+
+    let mut searcher = Searcher::new();
+    searcher.opening_book.set_disabled();
+    searcher.verbose = false;
+
+    let mut total_positions = 0;
+    for game in 1..=config.games {
+        let (positions, result) = generate_game(&mut searcher, &mut rng, config);
+        // A game stopped by a bad move has no result to learn from
+        if let Some(result) = result {
+            for (fen, score) in &positions {
+                writeln!(output, "{};{};{:.1}", fen, score, result)?;
+            }
+            total_positions += positions.len();
+        }
+        if game % 10 == 0 {
+            output.flush()?;
+            println!("{} games, {} positions ({:?})", game, total_positions, start.elapsed());
+        }
+    }
+    output.flush()?;
+    println!("Done: {} games, {} positions in {:?}", config.games, total_positions, start.elapsed());
+    Ok(())
+}
@@ -1,3 +1,4 @@
 pub mod perf_test;
 pub mod perft;
-pub mod selfplay;
\ No newline at end of file
+pub mod selfplay;
+pub mod datagen;
@@ -4,7 +4,7 @@ use pleco::Board;
 use pleco::board::movegen::{Legal, MoveGen};
 use pleco::core::mono_traits::AllGenType;
 use crate::core::Limiter;
-use crate::core::search::search::Searcher;
+use crate::core::search::search::{Searcher, STATISTICS};
 
 pub fn perf_test() {
     let mut duration = Duration::new(0, 0);
@@ -36,14 +36,17 @@ pub fn perf_test() {
     // let mut position = Board::from_fen("2kr3r/p1ppqpb1/bn2Qnp1/3PN3/1p2P3/2N5/PPPBBPPP/R3K2R b KQ - 3 2").unwrap();
     let target_depth = 8;
     let mut searcher = Searcher::new();
+    let mut nodes: u64 = 0;
     for str in test_fen_strings {
         let start = Instant::now();
         let mut board = Board::from_fen(str).unwrap();
         searcher.search(&mut board, Limiter::depth(target_depth));
+        nodes += unsafe { STATISTICS.nodes_searched } as u64;
 
         duration += start.elapsed();
     }
     println!("{:?}", duration);
+    println!("Nodes searched at the final depth: {}", nodes);
     // let m = searcher.search(&position);
     // println!("Move: {}", m);
 }
\ No newline at end of file
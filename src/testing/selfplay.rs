@@ -1,14 +1,56 @@
 use std::time::Duration;
-use pleco::{BitMove, Board};
-use pleco::board::movegen::{Legal, MoveGen};
-use pleco::core::mono_traits::AllGenType;
-use pleco::Player::{Black, White};
+use pleco::{BitMove, Board, Player};
+use pleco::Player::White;
 use crate::core::{Limiter, move_to_san};
 use crate::core::search::search::Searcher;
 
 
 pub static mut USE_RFP: bool = false;
 
+/// How a self-play game ended
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GameOutcome {
+    Checkmate { winner: Player },
+    /// Stalemate, repetition, fifty move rule or the ply limit
+    Draw,
+    /// The engine returned a move that isn't legal in the position, which stops the game
+    IllegalMove(BitMove),
+}
+
+impl GameOutcome {
+    /// 1 if white won, 0.5 for a draw and 0 if black won, nothing for an interrupted game
+    pub fn white_score(self) -> Option<f32> {
+        match self {
+            GameOutcome::Checkmate { winner: Player::White } => Some(1.0),
+            GameOutcome::Checkmate { winner: Player::Black } => Some(0.0),
+            GameOutcome::Draw => Some(0.5),
+            GameOutcome::IllegalMove(_) => None,
+        }
+    }
+}
+
+/// Plays a game from `board` until it ends, games lasting `max_plies` plies being drawn.
+/// `choose_move` searches the move of the side to move, and `on_move` sees each legal move
+/// right before it is played.
+pub fn play_game(board: &mut Board, max_plies: usize, mut choose_move: impl FnMut(&mut Board) -> BitMove, mut on_move: impl FnMut(&Board, BitMove)) -> GameOutcome {
+    let mut plies = 0;
+    loop {
+        if board.checkmate() {
+            return GameOutcome::Checkmate { winner: !board.turn() };
+        }
+        if board.stalemate() || board.threefold_repetition() || board.fifty_move_rule() || plies >= max_plies {
+            return GameOutcome::Draw;
+        }
+        let m = choose_move(board);
+        if !board.generate_moves().contains(&m) {
+            return GameOutcome::IllegalMove(m);
+        }
+        on_move(board, m);
+        board.apply_move(m);
+        plies += 1;
+    }
+}
+
 pub fn self_play_test(white_rsp: bool) -> i32 {
     
     let mut board = Board::start_pos();
@@ -20,58 +62,42 @@ pub fn self_play_test(white_rsp: bool) -> i32 {
     let mut i = 1;
 
     let mut pgn_str: String = "".into();
-    while !board.stalemate() && !board.checkmate() && !board.threefold_repetition() && !board.fifty_move_rule() {
-        let mut m = BitMove::null();
-        if (i - 1) % 2 == 1 - (white_rsp as usize)  {
-            m = searcher_rsp.search(&mut board, Limiter::time(Duration::from_secs_f32(2.0)));
-            
+    let outcome = play_game(&mut board, usize::MAX, |board| {
+        if (board.turn() == White) == white_rsp {
+            let m = searcher_rsp.search(board, Limiter::time(Duration::from_secs_f32(2.0)));
             unsafe { USE_RFP = true }
+            m
         } else {
-            m = searcher_non_rsp.search(&mut board, Limiter::time(Duration::from_secs_f32(2.0)));
-            
+            let m = searcher_non_rsp.search(board, Limiter::time(Duration::from_secs_f32(2.0)));
             unsafe { USE_RFP = false }
+            m
         }
-        
-        let legal = MoveGen::generate::<Legal, AllGenType>(&board);
-        if legal.iter().copied().collect::<Vec<BitMove>>().contains(&m) {
-            let mv = move_to_san(&board, &m);
-            if i % 2 == 1 {
-                pgn_str.push_str(format!("{}. ", (i+1)/2).as_str());
-            }
-            pgn_str.push_str(format!("{} ", mv).as_str());
-            board.apply_move(m);
-            searcher_rsp.opening_book.apply_move(m.to_string());
-            searcher_non_rsp.opening_book.apply_move(m.to_string());
-            
-            i += 1;
-        } else {
-            println!("{}", pgn_str);
-            println!("A bad move has been played: {}", m);
-            println!("Position {}", board.fen());
-            break
+    }, |board, m| {
+        let mv = move_to_san(board, &m);
+        if i % 2 == 1 {
+            pgn_str.push_str(format!("{}. ", (i+1)/2).as_str());
         }
+        pgn_str.push_str(format!("{} ", mv).as_str());
+        i += 1;
+    });
+    if let GameOutcome::IllegalMove(m) = outcome {
+        println!("{}", pgn_str);
+        println!("A bad move has been played: {}", m);
+        println!("Position {}", board.fen());
     }
     println!("{}", pgn_str);
     
-    if board.checkmate() {
-        if white_rsp {
-            return if board.turn() == White {
-                println!("Won NON RSP");
-                -1
-            } else {
-                println!("Won RSP");
-                1
-            }
-        } else if board.turn() == Black {
+    if let GameOutcome::Checkmate { winner } = outcome {
+        return if (winner == White) == white_rsp {
             println!("Won RSP");
-            return 1;
+            1
         } else {
             println!("Won NON RSP");
-            return -1;
-        }
+            -1
+        };
     }
-    return 0;
+    0
         
     // let mut f= fs::File::create("./game.pgn").unwrap();
     // f.write_all(pgn_str.as_utf8_bytes()).unwrap();
-}
\ No newline at end of file
+}
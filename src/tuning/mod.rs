@@ -0,0 +1,2 @@
+pub mod texel;
+pub mod nnue_trainer;
@@ -0,0 +1,326 @@
+use std::fs;
+use std::io;
+use std::thread;
+use std::time::Instant;
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use crate::core::eval::nnue::{NnueNetwork, EVAL_SCALE, INPUT_SIZE, QA, QB};
+
+const BETA1: f32 = 0.9;
+const BETA2: f32 = 0.999;
+const EPSILON: f32 = 1e-8;
+/// Share of the positions kept aside to measure the validation loss
+const VALIDATION_SPLIT: f64 = 0.1;
+
+pub struct TrainerConfig {
+    pub dataset: String,
+    pub output: String,
+    pub hidden_size: usize,
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f32,
+    /// How much the search score weighs against the game result in the target, from 0 to 1
+    pub lambda: f32,
+    pub threads: usize,
+    pub seed: u64,
+}
+
+impl TrainerConfig {
+    /// `train <dataset> [output] [--hidden n] [--epochs n] [--batch n] [--lr x] [--lambda x] [--threads n] [--seed n]`
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        let mut positional = vec![];
+        let mut config = Self {
+            dataset: String::new(),
+            output: "network.nnue".to_string(),
+            hidden_size: 256,
+            epochs: 30,
+            batch_size: 16384,
+            learning_rate: 0.001,
+            lambda: 0.5,
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            seed: 0,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--hidden" => config.hidden_size = args.next()?.parse().ok()?,
+                "--epochs" => config.epochs = args.next()?.parse().ok()?,
+                "--batch" => config.batch_size = args.next()?.parse().ok()?,
+                "--lr" => config.learning_rate = args.next()?.parse().ok()?,
+                "--lambda" => config.lambda = args.next()?.parse().ok()?,
+                "--threads" => config.threads = args.next()?.parse().ok()?,
+                "--seed" => config.seed = args.next()?.parse().ok()?,
+                _ => positional.push(arg.clone()),
+            }
+        }
+        config.dataset = positional.first()?.clone();
+        if let Some(output) = positional.get(1) {
+            config.output = output.clone();
+        }
+        config.threads = config.threads.max(1);
+        config.batch_size = config.batch_size.max(1);
+        Some(config)
+    }
+}
+
+struct Sample {
+    /// Active inputs, see `nnue::feature_index`
+    features: Vec<u16>,
+    /// Expected win probability for white
+    target: f32,
+}
+
+/// Active inputs of a FEN, read straight from the piece placement
+fn fen_features(fen: &str) -> Option<Vec<u16>> {
+    let placement = fen.split_whitespace().next()?;
+    let mut features = vec![];
+    for (i, rank) in placement.split('/').enumerate() {
+        if i >= 8 {
+            return None;
+        }
+        let mut file = 0;
+        for c in rank.chars() {
+            if let Some(skip) = c.to_digit(10) {
+                file += skip as usize;
+                continue;
+            }
+            let piece = "pnbrqk".find(c.to_ascii_lowercase())?;
+            let color = if c.is_ascii_uppercase() { 0 } else { 1 };
+            let sq = (7 - i) * 8 + file;
+            if file >= 8 {
+                return None;
+            }
+            features.push((color * 384 + piece * 64 + sq) as u16);
+            file += 1;
+        }
+    }
+    Some(features)
+}
+
+/// Win probability for a centipawn score, the same curve the tuner fits
+fn win_probability(eval: f32) -> f32 {
+    1.0 / (1.0 + 10f32.powf(-eval / 400.0))
+}
+
+/// Reads the `datagen` output: `fen;score;result` lines, from white's point of view
+fn load_dataset(path: &str, lambda: f32) -> io::Result<Vec<Sample>> {
+    let data = fs::read_to_string(path)?;
+    let mut samples = vec![];
+    for line in data.lines() {
+        let fields: Vec<&str> = line.trim().split(';').collect();
+        if fields.len() != 3 {
+            continue;
+        }
+        let (Some(features), Ok(score), Ok(result)) = (fen_features(fields[0]), fields[1].parse::<f32>(), fields[2].parse::<f32>()) else {
+            continue;
+        };
+        let target = lambda * win_probability(score) + (1.0 - lambda) * result;
+        samples.push(Sample { features, target });
+    }
+    Ok(samples)
+}
+
+/// Float version of the network, trained then quantized into an `NnueNetwork`
+#[derive(Clone)]
+struct Network {
+    hidden_size: usize,
+    feature_weights: Vec<f32>,
+    hidden_bias: Vec<f32>,
+    output_weights: Vec<f32>,
+    output_bias: f32,
+}
+
+impl Network {
+    fn random(hidden_size: usize, rng: &mut SmallRng) -> Self {
+        let feature_range = 1.0 / 32f32.sqrt();
+        let output_range = 1.0 / (hidden_size as f32).sqrt();
+        Self {
+            hidden_size,
+            feature_weights: (0..INPUT_SIZE * hidden_size).map(|_| rng.random_range(-feature_range..feature_range)).collect(),
+            hidden_bias: vec![0.0; hidden_size],
+            output_weights: (0..hidden_size).map(|_| rng.random_range(-output_range..output_range)).collect(),
+            output_bias: 0.0,
+        }
+    }
+    fn zeros(hidden_size: usize) -> Self {
+        Self {
+            hidden_size,
+            feature_weights: vec![0.0; INPUT_SIZE * hidden_size],
+            hidden_bias: vec![0.0; hidden_size],
+            output_weights: vec![0.0; hidden_size],
+            output_bias: 0.0,
+        }
+    }
+    /// Hidden layer sums and the output, in pawns of `EVAL_SCALE` centipawns
+    fn forward(&self, features: &[u16], accumulator: &mut [f32]) -> f32 {
+        accumulator.copy_from_slice(&self.hidden_bias);
+        for &f in features {
+            let row = &self.feature_weights[f as usize * self.hidden_size..(f as usize + 1) * self.hidden_size];
+            accumulator.iter_mut().zip(row).for_each(|(a, w)| *a += w);
+        }
+        accumulator.iter().zip(&self.output_weights).map(|(a, w)| a.clamp(0.0, 1.0) * w).sum::<f32>() + self.output_bias
+    }
+    fn loss(&self, sample: &Sample, accumulator: &mut [f32]) -> f32 {
+        let prediction = win_probability(self.forward(&sample.features, accumulator) * EVAL_SCALE as f32);
+        (prediction - sample.target).powi(2)
+    }
+    /// Adds the gradient of the loss of `sample` to `gradient`
+    fn backward(&self, sample: &Sample, accumulator: &mut [f32], gradient: &mut Network) {
+        let out = self.forward(&sample.features, accumulator);
+        let prediction = win_probability(out * EVAL_SCALE as f32);
+        // d/d_out of (sigmoid(out * scale * ln(10) / 400) - target)^2
+        let slope = EVAL_SCALE as f32 * std::f32::consts::LN_10 / 400.0;
+        let g = 2.0 * (prediction - sample.target) * prediction * (1.0 - prediction) * slope;
+
+        gradient.output_bias += g;
+        for j in 0..self.hidden_size {
+            let a = accumulator[j];
+            gradient.output_weights[j] += g * a.clamp(0.0, 1.0);
+            if a > 0.0 && a < 1.0 {
+                let d = g * self.output_weights[j];
+                gradient.hidden_bias[j] += d;
+                for &f in &sample.features {
+                    gradient.feature_weights[f as usize * self.hidden_size + j] += d;
+                }
+            }
+        }
+    }
+    fn params_mut(&mut self) -> impl Iterator<Item = &mut f32> {
+        self.feature_weights.iter_mut()
+            .chain(self.hidden_bias.iter_mut())
+            .chain(self.output_weights.iter_mut())
+            .chain(std::iter::once(&mut self.output_bias))
+    }
+    fn params(&self) -> impl Iterator<Item = &f32> {
+        self.feature_weights.iter()
+            .chain(self.hidden_bias.iter())
+            .chain(self.output_weights.iter())
+            .chain(std::iter::once(&self.output_bias))
+    }
+    /// Keeps the weights inside what the quantized network can represent,
+    /// so the export doesn't saturate
+    fn clip(&mut self) {
+        let max_hidden = i16::MAX as f32 / QA as f32;
+        let max_output = i16::MAX as f32 / QB as f32;
+        self.feature_weights.iter_mut().chain(self.hidden_bias.iter_mut()).for_each(|w| *w = w.clamp(-max_hidden, max_hidden));
+        self.output_weights.iter_mut().for_each(|w| *w = w.clamp(-max_output, max_output));
+    }
+    fn quantize(&self) -> NnueNetwork {
+        let q = |w: f32, scale: i32| (w * scale as f32).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        NnueNetwork {
+            hidden_size: self.hidden_size,
+            feature_weights: self.feature_weights.iter().map(|&w| q(w, QA)).collect(),
+            hidden_bias: self.hidden_bias.iter().map(|&w| q(w, QA)).collect(),
+            output_weights: self.output_weights.iter().map(|&w| q(w, QB)).collect(),
+            output_bias: (self.output_bias * (QA * QB) as f32).round() as i32,
+        }
+    }
+}
+
+/// Sums `f` over `samples` split across threads, adding the partial sums in order so the
+/// result doesn't depend on scheduling
+fn parallel_sum<T: Send, F>(samples: &[Sample], threads: usize, init: impl Fn() -> T + Sync, f: F, merge: impl Fn(&mut T, T)) -> T
+    where F: Fn(&Sample, &mut T) + Sync {
+    let chunk_size = samples.len().div_ceil(threads).max(1);
+    let partials: Vec<T> = thread::scope(|s| {
+        let handles: Vec<_> = samples.chunks(chunk_size).map(|chunk| {
+            let (init, f) = (&init, &f);
+            s.spawn(move || {
+                let mut total = init();
+                chunk.iter().for_each(|sample| f(sample, &mut total));
+                total
+            })
+        }).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let mut total = init();
+    for partial in partials {
+        merge(&mut total, partial);
+    }
+    total
+}
+
+/// Trains a network on `datagen` positions with Adam, printing the training and validation loss
+/// of each epoch (the latter also for the quantized network), and writes the quantized weights
+/// in the format `NnueNetwork::load` reads. A fixed seed always gives the same network.
+pub fn run_trainer(config: &TrainerConfig) -> io::Result<()> {
+    let start = Instant::now();
+    let mut rng = SmallRng::seed_from_u64(config.seed);
+    let mut samples = load_dataset(&config.dataset, config.lambda)?;
+    if samples.len() < 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the dataset has too few usable positions"));
+    }
+    samples.shuffle(&mut rng);
+    let validation_size = ((samples.len() as f64 * VALIDATION_SPLIT) as usize).max(1);
+    let (validation, training) = samples.split_at_mut(validation_size);
+    println!("Loaded {} training and {} validation positions", training.len(), validation.len());
+
+    let hidden_size = config.hidden_size;
+    let mut network = Network::random(hidden_size, &mut rng);
+    let mut moment = Network::zeros(hidden_size);
+    let mut velocity = Network::zeros(hidden_size);
+    let mut step = 0;
+
+    for epoch in 1..=config.epochs {
+        training.shuffle(&mut rng);
+        let mut training_loss = 0.0;
+        for batch in training.chunks(config.batch_size) {
+            let (gradient, loss) = parallel_sum(
+                batch,
+                config.threads,
+                || (Network::zeros(hidden_size), 0.0f64),
+                |sample, (gradient, loss)| {
+                    let mut accumulator = vec![0.0; hidden_size];
+                    network.backward(sample, &mut accumulator, gradient);
+                    *loss += network.loss(sample, &mut accumulator) as f64;
+                },
+                |(total, total_loss), (gradient, loss)| {
+                    total.params_mut().zip(gradient.params()).for_each(|(t, g)| *t += g);
+                    *total_loss += loss;
+                },
+            );
+            training_loss += loss;
+
+            step += 1;
+            let scale = 1.0 / batch.len() as f32;
+            let bias_correction1 = 1.0 - BETA1.powi(step);
+            let bias_correction2 = 1.0 - BETA2.powi(step);
+            for (((w, g), m), v) in network.params_mut().zip(gradient.params()).zip(moment.params_mut()).zip(velocity.params_mut()) {
+                let g = g * scale;
+                *m = BETA1 * *m + (1.0 - BETA1) * g;
+                *v = BETA2 * *v + (1.0 - BETA2) * g * g;
+                *w -= config.learning_rate * (*m / bias_correction1) / ((*v / bias_correction2).sqrt() + EPSILON);
+            }
+            network.clip();
+        }
+
+        let quantized = network.quantize();
+        let (validation_loss, quantized_loss) = parallel_sum(
+            validation,
+            config.threads,
+            || (0.0f64, 0.0f64),
+            |sample, (loss, quantized_loss)| {
+                let mut accumulator = vec![0.0; hidden_size];
+                *loss += network.loss(sample, &mut accumulator) as f64;
+                // Through the engine's own code, so the loss includes any i16 wraparound of the export
+                let eval = quantized.evaluate_features(sample.features.iter().map(|&f| f as usize)) as f32;
+                *quantized_loss += (win_probability(eval) - sample.target).powi(2) as f64;
+            },
+            |(total, total_quantized), (loss, quantized_loss)| {
+                *total += loss;
+                *total_quantized += quantized_loss;
+            },
+        );
+        println!(
+            "Epoch {}: training loss {:.6}, validation loss {:.6} (quantized {:.6}) ({:?})",
+            epoch,
+            training_loss / training.len() as f64,
+            validation_loss / validation.len() as f64,
+            quantized_loss / validation.len() as f64,
+            start.elapsed()
+        );
+        quantized.save(&config.output)?;
+    }
+    Ok(())
+}
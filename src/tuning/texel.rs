@@ -0,0 +1,348 @@
+use std::fs;
+use std::io;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::thread::Scope;
+use std::time::Instant;
+use pleco::{Board, Player};
+use pleco::core::GenTypes;
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde_json::Value;
+use crate::core::eval::evaluation::ClassicEvaluator;
+use crate::core::eval::Evaluator;
+use crate::core::eval::params::EvalParams;
+
+const QSEARCH_MAX_DEPTH: u8 = 8;
+/// Parameters that aren't evaluation weights and are left as they are: the phases have to keep
+/// adding up to `MAX_PHASE`, the others are averages, caps, divisors and scale factors
+const FIXED_PARAMS: [&str; 14] = [
+    "knight_phase",
+    "bishop_phase",
+    "rook_phase",
+    "queen_phase",
+    "knight_average_mobility",
+    "bishop_average_mobility",
+    "rook_average_mobility",
+    "queen_average_mobility",
+    "piece_square_weight",
+    "max_attack_units",
+    "king_danger_divisor",
+    "scale_opposite_bishops",
+    "scale_opposite_bishops_with_pieces",
+    "scale_no_pawns_small_advantage",
+];
+
+pub struct TunerConfig {
+    pub dataset: String,
+    pub output: String,
+    /// Parameters to start from, the built-in ones if not given
+    pub start_params: Option<String>,
+    pub threads: usize,
+    pub seed: u64,
+    pub iterations: usize,
+    /// Resolve captures with a quiescence search instead of using the static eval
+    pub qsearch: bool,
+}
+
+impl TunerConfig {
+    /// `tuner <dataset> [output] [--params file] [--threads n] [--seed n] [--iterations n] [--qsearch]`
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        let mut positional = vec![];
+        let mut config = Self {
+            dataset: String::new(),
+            output: "tuned_params.json".to_string(),
+            start_params: None,
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            seed: 0,
+            iterations: 100,
+            qsearch: false,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--params" => config.start_params = Some(args.next()?.clone()),
+                "--threads" => config.threads = args.next()?.parse().ok()?,
+                "--seed" => config.seed = args.next()?.parse().ok()?,
+                "--iterations" => config.iterations = args.next()?.parse().ok()?,
+                "--qsearch" => config.qsearch = true,
+                _ => positional.push(arg.clone()),
+            }
+        }
+        config.dataset = positional.first()?.clone();
+        if let Some(output) = positional.get(1) {
+            config.output = output.clone();
+        }
+        config.threads = config.threads.max(1);
+        Some(config)
+    }
+}
+
+struct TrainingPosition {
+    board: Board,
+    /// Game result from white's point of view: 1 win, 0.5 draw, 0 loss
+    result: f64,
+}
+
+/// Parses a line with a FEN followed by the game result, either as
+/// `1-0`/`1/2-1/2`/`0-1` or as `1.0`/`0.5`/`0.0`, optionally in brackets or quotes.
+/// Lines with `;` separated fields (like the `datagen` output) take the first field as the FEN
+/// and the last one as the result.
+fn parse_line(line: &str) -> Option<(&str, f64)> {
+    let line = line.trim();
+    let (fen, result) = match line.split_once(';') {
+        Some((fen, rest)) => (fen, rest.rsplit(';').next()?),
+        None => line.rsplit_once(char::is_whitespace)?,
+    };
+    let result = result.trim_matches(|c| c == '[' || c == ']' || c == '"');
+    let result = match result {
+        "1-0" => 1.0,
+        "0-1" => 0.0,
+        "1/2-1/2" => 0.5,
+        r => r.parse().ok()?,
+    };
+    Some((fen.trim(), result))
+}
+
+fn load_dataset(path: &str) -> io::Result<Vec<TrainingPosition>> {
+    let data = fs::read_to_string(path)?;
+    let mut positions = vec![];
+    for line in data.lines() {
+        if let Some((fen, result)) = parse_line(line) {
+            if let Ok(board) = Board::from_fen(fen) {
+                positions.push(TrainingPosition { board, result });
+            }
+        }
+    }
+    Ok(positions)
+}
+
+fn sigmoid(eval: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+fn quiescence(evaluator: &mut ClassicEvaluator, board: &mut Board, mut alpha: i32, beta: i32, depth_left: u8) -> i32 {
+    let stand_pat = evaluator.evaluate_board(board);
+    if stand_pat >= beta {
+        return beta;
+    }
+    alpha = alpha.max(stand_pat);
+    if depth_left == 0 {
+        return alpha;
+    }
+    for m in board.generate_moves_of_type(GenTypes::Captures) {
+        board.apply_move(m);
+        let score = -quiescence(evaluator, board, -beta, -alpha, depth_left - 1);
+        board.undo_move();
+        if score >= beta {
+            return beta;
+        }
+        alpha = alpha.max(score);
+    }
+    alpha
+}
+
+fn white_eval(evaluator: &mut ClassicEvaluator, board: &Board, qsearch: bool) -> i32 {
+    let eval = if qsearch {
+        let mut board = board.shallow_clone();
+        quiescence(evaluator, &mut board, -i32::MAX, i32::MAX, QSEARCH_MAX_DEPTH)
+    } else {
+        evaluator.evaluate_board(board)
+    };
+    if board.turn() == Player::White { eval } else { -eval }
+}
+
+/// One thread per chunk of the dataset, each keeping its evaluator between error computations
+/// and only swapping its parameters. The threads stop once the workers are dropped.
+struct ErrorWorkers {
+    requests: Vec<mpsc::Sender<(Arc<EvalParams>, f64)>>,
+    sums: Vec<mpsc::Receiver<f64>>,
+    total: usize,
+}
+
+impl ErrorWorkers {
+    fn spawn<'scope, 'env>(scope: &'scope Scope<'scope, 'env>, chunks: &[&'env [TrainingPosition]], qsearch: bool) -> Self {
+        let mut workers = Self { requests: vec![], sums: vec![], total: chunks.iter().map(|c| c.len()).sum() };
+        for &chunk in chunks {
+            let (request_sender, requests) = mpsc::channel::<(Arc<EvalParams>, f64)>();
+            let (sum_sender, sums) = mpsc::channel();
+            scope.spawn(move || {
+                let mut evaluator = ClassicEvaluator::new();
+                for (params, k) in requests {
+                    evaluator.set_params(params.as_ref().clone());
+                    let sum = chunk.iter()
+                        .map(|p| (p.result - sigmoid(white_eval(&mut evaluator, &p.board, qsearch) as f64, k)).powi(2))
+                        .sum::<f64>();
+                    if sum_sender.send(sum).is_err() {
+                        break;
+                    }
+                }
+            });
+            workers.requests.push(request_sender);
+            workers.sums.push(sums);
+        }
+        workers
+    }
+    /// Mean squared error between the results and the predicted scores.
+    /// The sums of the chunks are added in order, so the result doesn't depend on thread scheduling.
+    fn mean_error(&self, params: &EvalParams, k: f64) -> f64 {
+        let params = Arc::new(params.clone());
+        for request in &self.requests {
+            request.send((params.clone(), k)).unwrap();
+        }
+        let sum: f64 = self.sums.iter().map(|sums| sums.recv().unwrap()).sum();
+        sum / self.total.max(1) as f64
+    }
+}
+
+/// Scaling constant of the sigmoid that best fits the current evaluation
+fn find_k(workers: &ErrorWorkers, params: &EvalParams) -> f64 {
+    let mut best_k = 1.0;
+    let mut best_error = workers.mean_error(params, best_k);
+    let mut step = 0.5;
+    while step > 0.001 {
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for k in [best_k - step, best_k + step] {
+                if k <= 0.0 {
+                    continue;
+                }
+                let error = workers.mean_error(params, k);
+                if error < best_error {
+                    best_error = error;
+                    best_k = k;
+                    improved = true;
+                }
+            }
+        }
+        step /= 2.0;
+    }
+    best_k
+}
+
+fn flatten(value: &Value, out: &mut Vec<i64>) {
+    match value {
+        Value::Number(n) => out.push(n.as_i64().unwrap_or(0)),
+        Value::Array(values) => values.iter().for_each(|v| flatten(v, out)),
+        Value::Object(map) => map.values().for_each(|v| flatten(v, out)),
+        _ => {}
+    }
+}
+fn unflatten(value: &mut Value, values: &[i64], index: &mut usize) {
+    match value {
+        Value::Number(n) => {
+            *n = values[*index].into();
+            *index += 1;
+        }
+        Value::Array(array) => array.iter_mut().for_each(|v| unflatten(v, values, index)),
+        Value::Object(map) => map.values_mut().for_each(|v| unflatten(v, values, index)),
+        _ => {}
+    }
+}
+fn is_tuned(name: &str) -> bool {
+    !FIXED_PARAMS.contains(&name)
+}
+/// Every tuned weight of the parameters as a flat list, in a fixed order
+fn params_to_vec(params: &EvalParams) -> Vec<i64> {
+    let mut json = serde_json::to_value(params).unwrap();
+    json.as_object_mut().unwrap().retain(|name, _| is_tuned(name));
+    let mut values = vec![];
+    flatten(&json, &mut values);
+    values
+}
+/// The template with its tuned weights replaced by `values`
+fn vec_to_params(template: &EvalParams, values: &[i64]) -> EvalParams {
+    let mut json = serde_json::to_value(template).unwrap();
+    let mut index = 0;
+    json.as_object_mut().unwrap().iter_mut()
+        .filter(|(name, _)| is_tuned(name))
+        .for_each(|(_, value)| unflatten(value, values, &mut index));
+    serde_json::from_value(json).unwrap()
+}
+
+/// Texel tuning: fits the evaluation to the game results of a set of quiet positions by
+/// nudging every weight (but the `FIXED_PARAMS`) up or down one unit at a time, keeping whatever lowers the error.
+/// The best parameters so far are written to the output file after every iteration.
+pub fn run_tuner(config: &TunerConfig) -> io::Result<EvalParams> {
+    let start = Instant::now();
+    let positions = load_dataset(&config.dataset)?;
+    if positions.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the dataset has no usable positions"));
+    }
+    println!("Loaded {} positions", positions.len());
+
+    let chunk_size = positions.len().div_ceil(config.threads);
+    let chunks: Vec<&[TrainingPosition]> = positions.chunks(chunk_size).collect();
+
+    let template = match &config.start_params {
+        Some(path) => EvalParams::load(path)?,
+        None => EvalParams::default(),
+    };
+
+    thread::scope(|scope| {
+        let workers = ErrorWorkers::spawn(scope, &chunks, config.qsearch);
+        let k = find_k(&workers, &template);
+        println!("Fitted K = {:.4}", k);
+
+        let mut best = params_to_vec(&template);
+        let mut best_error = workers.mean_error(&template, k);
+        println!("Initial error: {:.8}", best_error);
+
+        let mut rng = SmallRng::seed_from_u64(config.seed);
+        let mut order: Vec<usize> = (0..best.len()).collect();
+        for iteration in 1..=config.iterations {
+            order.shuffle(&mut rng);
+            let mut improved = false;
+            for &i in &order {
+                for delta in [1, -1] {
+                    let mut candidate = best.clone();
+                    candidate[i] += delta;
+                    let error = workers.mean_error(&vec_to_params(&template, &candidate), k);
+                    if error < best_error {
+                        best_error = error;
+                        best = candidate;
+                        improved = true;
+                        break;
+                    }
+                }
+            }
+
+            let params = vec_to_params(&template, &best);
+            params.save(&config.output)?;
+            println!("Iteration {}: error {:.8} ({:?})", iteration, best_error, start.elapsed());
+            if !improved {
+                break;
+            }
+        }
+        Ok(vec_to_params(&template, &best))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::eval::params::EvalParams;
+    use super::{params_to_vec, vec_to_params};
+
+    #[test]
+    fn params_survive_the_round_trip() {
+        let params = EvalParams::default();
+        let values = params_to_vec(&params);
+        assert_eq!(params_to_vec(&vec_to_params(&params, &values)), values);
+    }
+
+    #[test]
+    fn fixed_params_are_not_tuned() {
+        let params = EvalParams::default();
+        let values: Vec<i64> = params_to_vec(&params).iter().map(|v| v + 7).collect();
+        let tuned = vec_to_params(&params, &values);
+        assert_eq!(tuned.knight_phase, params.knight_phase);
+        assert_eq!(tuned.queen_phase, params.queen_phase);
+        assert_eq!(tuned.rook_average_mobility, params.rook_average_mobility);
+        assert_eq!(tuned.piece_square_weight, params.piece_square_weight);
+        assert_eq!(tuned.king_danger_divisor, params.king_danger_divisor);
+        assert_eq!(tuned.scale_opposite_bishops, params.scale_opposite_bishops);
+        assert_eq!(tuned.isolated_pawn_penalty.mg(), params.isolated_pawn_penalty.mg() + 7);
+    }
+}
@@ -13,7 +13,14 @@ use pleco::core::piece_move::{MoveFlag, PreMoveInfo};
 use pleco::File::B;
 use vampirc_uci::{parse, parse_one, UciFen, UciMessage, UciTimeControl};
 use vampirc_uci::Rule::message;
+use std::sync::Arc;
+use crate::core::eval::evaluation::ClassicEvaluator;
+use crate::core::eval::Evaluator;
+use crate::core::eval::eval_cache::EvalCache;
+use crate::core::eval::nnue::{NnueEvaluator, NnueNetwork};
+use crate::core::eval::params::EvalParams;
 use crate::core::Limiter;
+use crate::core::search::mate_search::MateSearchResult;
 use crate::core::search::search::{MAX_DEPTH, Searcher};
 
 pub fn uci_loop() -> Result<(), io::Error>{
@@ -23,14 +30,42 @@ pub fn uci_loop() -> Result<(), io::Error>{
 
     println!("Loading engine...");
     let mut searcher = Searcher::new();
+    let mut eval_params = EvalParams::default();
+    let mut network: Option<Arc<NnueNetwork>> = None;
+    let mut use_nnue = false;
     println!("Engine loaded");
     let stdin = stdin();
     let mut stdout = stdout();
     for line in stdin.lines() {
-        let msg: UciMessage = parse_one(&line.unwrap());
+        let line = line.unwrap();
+        // Debug commands outside of the UCI protocol
+        if line.trim() == "eval" {
+            let trace = ClassicEvaluator::with_params(eval_params.clone()).trace(&board);
+            println!("{}", trace);
+            if use_nnue && network.is_some() {
+                let eval = searcher.evaluator.evaluate_board(&board);
+                let eval = if board.turn() == Player::White { eval } else { -eval };
+                println!("NNUE evaluation: {:.2} (white side)", eval as f64 / 100.0);
+            }
+            stdout.flush()?;
+            continue;
+        }
+        let msg: UciMessage = parse_one(&line);
         match msg {
             UciMessage::Uci => {
-                let _ = stdout.write(b"id name CheRs2\nid author Victor Alan\nuciok\n")?;
+                let _ = stdout.write(b"id name CheRs2\nid author Victor Alan\n")?;
+                let _ = stdout.write(b"option name SyzygyPath type string default <empty>\n")?;
+                let _ = stdout.write(b"option name SyzygyProbeDepth type spin default 1 min 1 max 100\n")?;
+                let _ = stdout.write(b"option name SyzygyProbeLimit type spin default 7 min 0 max 7\n")?;
+                let _ = stdout.write(b"option name EndgameTablePath type string default <empty>\n")?;
+                let _ = stdout.write(b"option name EvalFile type string default <empty>\n")?;
+                let _ = stdout.write(b"option name UseNNUE type check default false\n")?;
+                let _ = stdout.write(b"option name NNUEFile type string default <empty>\n")?;
+                let _ = stdout.write(b"option name EvalCache type spin default 4 min 0 max 1024\n")?;
+                let _ = stdout.write(b"option name BookFile type string default <empty>\n")?;
+                let _ = stdout.write(b"option name BookStrategy type combo default Weighted var MostPlayed var BestScore var Weighted var Variety\n")?;
+                let _ = stdout.write(b"option name BookTemperature type spin default 50 min 1 max 1000\n")?;
+                let _ = stdout.write(b"uciok\n")?;
             }
             UciMessage::Debug(_) => {}
             UciMessage::IsReady => {
@@ -40,21 +75,106 @@ pub fn uci_loop() -> Result<(), io::Error>{
             UciMessage::Position { startpos, fen, moves } => {
                 println!("{:?}", fen);
                 if startpos {
-                    searcher.opening_book.set_enabled();
                     board = Board::start_pos();
                 } else {
-                    println!("Disabled!");
-                    searcher.opening_book.set_disabled();
                     board = Board::from_fen(fen.unwrap().0.as_str()).unwrap();
                 }
                 for mve in moves {
-                    if startpos {
-                        searcher.opening_book.apply_move(mve.to_string());
-                    }
                     board.apply_uci_move(&mve.to_string());
                 }
             }
-            UciMessage::SetOption { .. } => {}
+            UciMessage::SetOption { name, value } => {
+                let value = value.unwrap_or_default();
+                match name.as_str() {
+                    "SyzygyPath" => {
+                        if value.is_empty() || value == "<empty>" {
+                            searcher.tablebase.clear();
+                        } else {
+                            match searcher.tablebase.load(&value) {
+                                Ok(count) => println!("info string found {} tablebases", count),
+                                Err(e) => println!("info string could not load tablebases: {}", e),
+                            }
+                        }
+                    }
+                    "EndgameTablePath" => {
+                        if value.is_empty() || value == "<empty>" {
+                            searcher.endgame_tables.clear();
+                        } else {
+                            match searcher.endgame_tables.load_dir(&value) {
+                                Ok(count) => println!("info string loaded {} endgame tables", count),
+                                Err(e) => println!("info string could not load endgame tables: {}", e),
+                            }
+                        }
+                    }
+                    "EvalFile" => {
+                        if value.is_empty() || value == "<empty>" {
+                            eval_params = EvalParams::default();
+                        } else {
+                            match EvalParams::load(&value) {
+                                Ok(params) => eval_params = params,
+                                Err(e) => println!("info string could not load eval file: {}", e),
+                            }
+                        }
+                        searcher.set_evaluator(select_evaluator(use_nnue, &network, &eval_params));
+                    }
+                    "UseNNUE" => {
+                        use_nnue = value == "true";
+                        if use_nnue && network.is_none() {
+                            println!("info string no network loaded, set NNUEFile first");
+                        }
+                        searcher.set_evaluator(select_evaluator(use_nnue, &network, &eval_params));
+                    }
+                    "NNUEFile" => {
+                        if value.is_empty() || value == "<empty>" {
+                            network = None;
+                        } else {
+                            match NnueNetwork::load(&value) {
+                                Ok(loaded) => network = Some(Arc::new(loaded)),
+                                Err(e) => println!("info string could not load network: {}", e),
+                            }
+                        }
+                        searcher.set_evaluator(select_evaluator(use_nnue, &network, &eval_params));
+                    }
+                    "BookFile" => {
+                        if value.is_empty() || value == "<empty>" {
+                            searcher.opening_book.clear_polyglot();
+                        } else {
+                            match searcher.opening_book.load_polyglot(&value) {
+                                Ok(count) => println!("info string loaded {} book entries", count),
+                                Err(e) => println!("info string could not load book: {}", e),
+                            }
+                        }
+                    }
+                    "BookStrategy" => {
+                        match value.parse() {
+                            Ok(strategy) => searcher.opening_book.selector.strategy = strategy,
+                            Err(()) => println!("info string unknown book strategy: {}", value),
+                        }
+                    }
+                    "BookTemperature" => {
+                        // In hundredths, UCI spins are integers
+                        if let Ok(percent) = value.parse::<u32>() {
+                            searcher.opening_book.selector.temperature = percent.max(1) as f64 / 100.0;
+                        }
+                    }
+                    "EvalCache" => {
+                        if let Ok(size_mb) = value.parse() {
+                            searcher.eval_cache = EvalCache::new(size_mb);
+                        }
+                    }
+                    "SyzygyProbeDepth" => {
+                        if let Ok(depth) = value.parse() {
+                            searcher.tablebase.probe_depth = depth;
+                        }
+                    }
+                    "SyzygyProbeLimit" => {
+                        if let Ok(limit) = value.parse() {
+                            searcher.tablebase.probe_limit = limit;
+                        }
+                    }
+                    _ => {}
+                }
+            }
             UciMessage::UciNewGame => {
                 board = Board::start_pos();
                 // searcher = Searcher::new(TARGET_DEPTH, TARGET_TIME);
@@ -68,8 +188,25 @@ pub fn uci_loop() -> Result<(), io::Error>{
             }
             UciMessage::Go { time_control, search_control } => {
                 let mut target_depth = None;
+                let mut target_mate = None;
                 if let Some(search_control) = search_control {
                     target_depth = search_control.depth;
+                    target_mate = search_control.mate;
+                }
+
+                if let Some(mate_moves) = target_mate {
+                    match searcher.find_mate(&mut board, mate_moves) {
+                        MateSearchResult::Mate(line) => {
+                            let pv = line.iter().map(|m| m.stringify()).collect::<Vec<String>>().join(" ");
+                            let response = format!("info score mate {} pv {}\nbestmove {}\n", (line.len() + 1) / 2, pv, line[0].stringify());
+                            let _ = stdout.write(response.as_bytes())?;
+                            stdout.flush()?;
+                            continue;
+                        }
+                        MateSearchResult::NoMate(n) => {
+                            println!("info string no mate within {}", n);
+                        }
+                    }
                 }
 
                 let start = Instant::now();
@@ -91,8 +228,9 @@ pub fn uci_loop() -> Result<(), io::Error>{
                         moves_to_go, ..
                     } => {
                         
+                        // Spend more time right after the opening, easing off over the next ten moves
                         let mut n_moves= 0;
-                        if !searcher.opening_book.is_enabled() {
+                        if board.ply() > 10 {
                             n_moves = min(board.ply() - 10, 10);
                         }
 
@@ -130,4 +268,13 @@ pub fn uci_loop() -> Result<(), io::Error>{
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// The evaluator picked by the `UseNNUE`, `NNUEFile` and `EvalFile` options,
+/// falling back to the classic one while no network is loaded
+fn select_evaluator(use_nnue: bool, network: &Option<Arc<NnueNetwork>>, params: &EvalParams) -> Box<dyn Evaluator> {
+    match network {
+        Some(network) if use_nnue => Box::new(NnueEvaluator::new(network.clone())),
+        _ => Box::new(ClassicEvaluator::with_params(params.clone())),
+    }
+}